@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use tokio::fs;
 
 use crate::error::IoErr;
@@ -7,8 +9,68 @@ pub const DEVICE_ADDR: &'static str = "0.0.0.0:3655";
 
 pub const SQLITE_PATH: &'static str = "sqlite://./data/data.db?mode=rwc";
 pub const HTML_PATH: &'static str = "./data/html";
+pub const LOG_PATH: &str = "./data/logs";
+pub const FIRMWARE_PATH: &str = "./data/firmware";
+
+pub const LOG_CHUNK_SIZE: usize = 64 * 1024;
+pub const LOG_MAX_COUNT: i64 = 20;
+pub const LOG_MAX_DAYS: i64 = 30;
+// 单个日志大小上限, 每台设备同时上传的日志数上限, 超过时间仍未完成的上传视为放弃
+pub const LOG_MAX_SIZE: i64 = 64 * 1024 * 1024;
+pub const LOG_MAX_UPLOADING: i64 = 2;
+pub const LOG_UPLOAD_EXPIRE_SECS: i64 = 3600;
+
+pub const FIRMWARE_CHUNK_SIZE: usize = 32 * 1024;
+
+pub const TELEMETRY_RAW_DAYS: i64 = 7;
+pub const TELEMETRY_HOUR_DAYS: i64 = 90;
+
+// 同一地址或MAC在窗口期内认证失败超过次数后拒绝登录
+pub const AUTH_FAIL_MAX: u32 = 5;
+pub const AUTH_FAIL_WINDOW_SECS: i64 = 300;
+// 失败计数缓存上限, 超出时先清理过期项, 仍满则淘汰最早的窗口
+pub const AUTH_FAIL_CACHE_SIZE: usize = 10_000;
+
+// 待审批和自动登记后尚未确认密钥的设备数上限
+pub const PROVISION_PENDING_MAX: i64 = 1000;
+
+static AUTO_APPROVE: OnceLock<bool> = OnceLock::new();
+
+// 环境变量 ORANGE_PROVISION_AUTO_APPROVE=1 时未知设备自动登记, 仅用于实验环境
+pub fn provision_auto_approve() -> bool {
+    *AUTO_APPROVE.get_or_init(|| {
+        std::env::var("ORANGE_PROVISION_AUTO_APPROVE")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+    })
+}
+
+pub const SESSION_EXPIRE_SECS: i64 = 7 * 24 * 3600;
+pub const PASSWORD_ROUNDS: u32 = 100_000;
+// 首次启动没有任何账号时创建, 密码取环境变量 ORANGE_BOOTSTRAP_PASSWORD,
+// 未设置时随机生成并写入 BOOTSTRAP_PASSWORD_PATH, 仅文件所有者可读
+pub const BOOTSTRAP_ADMIN: &str = "admin";
+pub const BOOTSTRAP_PASSWORD_PATH: &str = "./data/bootstrap_password";
+
+// 同一地址或账号在窗口期内登录失败超过次数后拒绝登录
+pub const LOGIN_FAIL_IP_MAX: u32 = 20;
+pub const LOGIN_FAIL_USER_MAX: u32 = 5;
+pub const LOGIN_FAIL_WINDOW_SECS: i64 = 300;
+
+// 推送事件保留最近条数, 用于客户端断线重连后补发
+pub const EVENT_REPLAY_SIZE: usize = 1000;
+pub const EVENT_CHANNEL_SIZE: usize = 256;
+pub const EVENT_KEEPALIVE_SECS: u64 = 15;
+// 每个客户端待发送的事件数上限, 超时仍写不进去时断开, 客户端重连后从补发缓存取回
+pub const EVENT_CLIENT_BUFFER: usize = 64;
+pub const EVENT_SEND_TIMEOUT_SECS: u64 = 10;
+
+pub const DEVICE_TIMEZONE: &str = "Asia/Shanghai";
+pub const DEVICE_UTC_OFFSET_MINUTES: i32 = 480;
 
 pub async fn init() -> Result<(), IoErr> {
     fs::create_dir_all(HTML_PATH).await?;
+    fs::create_dir_all(LOG_PATH).await?;
+    fs::create_dir_all(FIRMWARE_PATH).await?;
     Ok(())
 }
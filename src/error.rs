@@ -28,36 +28,55 @@ pub enum AppErr {
     #[error("wrap:{0}")]
     Wrap(Cow<'static, str>),
 
+    // 设备返回的错误
     #[error("custom:{0}")]
     Custom(ErrInfo),
 
+    // 服务端按错误码分类的错误
+    #[error("{1}")]
+    Code(i32, Cow<'static, str>),
+
     #[error("cbor:{0}")]
     Cbor(#[from] serde_cbor::Error),
 
+    #[error("json:{0}")]
+    Json(#[from] serde_json::Error),
+
     #[error("proto:{0}")]
     Proto(&'static str),
 }
 
 impl AppErr {
 
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Custom(info) => info.err_code,
+            Self::Code(code, _) => *code,
+            Self::Sqlx(SqlxErr::RowNotFound) => err_code::NOT_FOUND,
+            Self::Sqlx(SqlxErr::Database(e)) if e.is_unique_violation() => err_code::CONFLICT,
+            Self::Cbor(_) | Self::Json(_) => err_code::VALIDATION,
+            _ => err_code::UNKNOWN,
+        }
+    }
+
     pub fn into_info(self) -> ErrInfo {
         match self {
             Self::Custom(info) => info,
-            _ => ErrInfo { err_code: -1, err_msg: self.to_string() }
+            _ => self.to_info(),
         }
     }
 
+    pub fn to_info(&self) -> ErrInfo {
+        let err_msg = match self {
+            Self::Custom(info) => info.err_msg.clone(),
+            Self::Sqlx(SqlxErr::RowNotFound) => "数据不存在".to_string(),
+            _ => self.to_string(),
+        };
+        ErrInfo { err_code: self.code(), err_msg }
+    }
+
     pub fn serial_to_vec(&self) -> Vec<u8> {
-        match self {
-            Self::Custom(info) => serde_cbor::to_vec(info).unwrap(),
-            _ => {
-                let info = ErrInfo {
-                    err_code: -1,
-                    err_msg: self.to_string(),
-                };
-                serde_cbor::to_vec(&info).unwrap()
-            }
-        }
+        serde_cbor::to_vec(&self.to_info()).unwrap()
     }
 }
 
@@ -68,16 +87,51 @@ pub fn serial_to_vec<T: Serialize>(ret: Result<T, AppErr>) -> Vec<u8> {
     }
 }
 
-pub fn proto_err<T>(msg: &'static str) -> Result<T, AppErr> {
-    Err(AppErr::Proto(msg))
+// ErrInfo.err_code 的取值, 设备返回的错误码原样透传
+pub mod err_code {
+    pub const UNKNOWN: i32 = -1;
+    pub const DEVICE_OFFLINE: i32 = 1001;
+    pub const DEVICE_TIMEOUT: i32 = 1002;
+    pub const NOT_FOUND: i32 = 1003;
+    pub const VALIDATION: i32 = 1004;
+    pub const CONFLICT: i32 = 1005;
+    pub const UNAUTHORIZED: i32 = 1006;
+    pub const PERMISSION_DENIED: i32 = 1007;
+    pub const PAYLOAD_TOO_LARGE: i32 = 1008;
+    pub const BUSY: i32 = 1009;
+}
+
+pub fn code_err<T>(code: i32, msg: &'static str) -> Result<T, AppErr> {
+    Err(AppErr::Code(code, Cow::Borrowed(msg)))
+}
+
+pub fn code_errs<T>(code: i32, msg: String) -> Result<T, AppErr> {
+    Err(AppErr::Code(code, Cow::Owned(msg)))
+}
+
+pub fn not_found<T>(msg: &'static str) -> Result<T, AppErr> {
+    code_err(err_code::NOT_FOUND, msg)
+}
+
+pub fn conflict<T>(msg: &'static str) -> Result<T, AppErr> {
+    code_err(err_code::CONFLICT, msg)
 }
 
-pub fn error<T>(msg: &'static str) -> Result<T, AppErr> {
-    Err(AppErr::Wrap(Cow::Borrowed(msg)))
+pub fn permission_denied<T>(msg: &'static str) -> Result<T, AppErr> {
+    code_err(err_code::PERMISSION_DENIED, msg)
+}
+
+pub fn unauthorized<T>(msg: &'static str) -> Result<T, AppErr> {
+    code_err(err_code::UNAUTHORIZED, msg)
+}
+
+pub fn proto_err<T>(msg: &'static str) -> Result<T, AppErr> {
+    Err(AppErr::Proto(msg))
 }
 
-pub fn errors<T>(msg: String) -> Result<T, AppErr> {
-    Err(AppErr::Wrap(Cow::Owned(msg)))
+// 参数校验失败
+pub fn validation<T>(msg: &'static str) -> Result<T, AppErr> {
+    code_err(err_code::VALIDATION, msg)
 }
 
 pub trait ErrorExt<T> {
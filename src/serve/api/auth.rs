@@ -0,0 +1,147 @@
+use std::{net::SocketAddr, sync::OnceLock, time::Duration};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::Sha256;
+
+use super::{cmd, exec_logged, get_conn, provision, Operation};
+use crate::{
+    config::{AUTH_FAIL_CACHE_SIZE, AUTH_FAIL_MAX, AUTH_FAIL_WINDOW_SECS},
+    error::{not_found, unauthorized, AppErr, ErrorExt},
+    serve::conn::SharedConn,
+    store,
+    utils::{current_timestamp, limit::FailLimiter, to_hex},
+};
+
+const NONCE_SIZE: usize = 32;
+const SECRET_SIZE: usize = 32;
+const ROTATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ChallengeReq {
+    pub mac_addr: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ChallengeRes {
+    pub nonce: ByteBuf,
+}
+
+// key 为对端IP或MAC
+static FAILS: OnceLock<FailLimiter> = OnceLock::new();
+
+fn fails() -> &'static FailLimiter {
+    FAILS.get_or_init(|| FailLimiter::new(AUTH_FAIL_MAX, AUTH_FAIL_WINDOW_SECS, AUTH_FAIL_CACHE_SIZE))
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut buf);
+    buf
+}
+
+pub(super) fn challenge(addr: &SocketAddr, mac_addr: &str) -> Result<Vec<u8>, AppErr> {
+    let now = current_timestamp();
+    if fails().is_limited(&addr.ip().to_string(), now) || fails().is_limited(mac_addr, now) {
+        return unauthorized("认证失败次数过多, 请稍后重试");
+    }
+    Ok(random_bytes(NONCE_SIZE))
+}
+
+pub(super) async fn fail<T>(addr: &SocketAddr, mac_addr: &str, reason: &'static str) -> Result<T, AppErr> {
+    let now = current_timestamp();
+    fails().add(addr.ip().to_string(), now);
+    fails().add(mac_addr.to_string(), now);
+    store::auth::add_fail(mac_addr, &addr.to_string(), reason)
+        .await
+        .print_if_err();
+    unauthorized(reason)
+}
+
+// signature = HMAC-SHA256(secret, nonce || mac_addr)
+// 返回设备 id 以及是否在本次登录中自动登记
+pub(super) async fn verify(
+    addr: &SocketAddr,
+    mac_addr: &str,
+    nonce: &[u8],
+    signature: &[u8],
+) -> Result<(i64, bool), AppErr> {
+    let id = match store::device::get_id_by_mac(mac_addr).await? {
+        Some(id) => id,
+        None => return Ok((provision::on_unknown(addr, mac_addr).await?, true)),
+    };
+    let secrets = match store::auth::get_secrets(id).await? {
+        Some(secrets) => secrets,
+        None => return fail(addr, mac_addr, "设备未分配密钥").await,
+    };
+    if !check(&secrets.secret, nonce, mac_addr, signature)? {
+        let pending = secrets.pending.filter(|pending| {
+            check(pending, nonce, mac_addr, signature).unwrap_or(false)
+        });
+        match pending {
+            Some(pending) => store::auth::promote(id, &pending).await?,
+            None => return fail(addr, mac_addr, "签名校验失败").await,
+        }
+    }
+    fails().clear(&addr.ip().to_string());
+    fails().clear(mac_addr);
+    Ok((id, false))
+}
+
+fn check(secret: &[u8], nonce: &[u8], mac_addr: &str, signature: &[u8]) -> Result<bool, AppErr> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).wrap()?;
+    mac.update(nonce);
+    mac.update(mac_addr.as_bytes());
+    Ok(mac.verify_slice(signature).is_ok())
+}
+
+pub(super) fn new_secret() -> Vec<u8> {
+    random_bytes(SECRET_SIZE)
+}
+
+// 出厂或设备丢失密钥时使用, 返回的密钥需写入设备
+pub async fn issue(device_id: i64) -> Result<String, AppErr> {
+    store::device::get(device_id).await?;
+    let secret = new_secret();
+    store::auth::set_secret(device_id, &secret).await?;
+    Ok(to_hex(&secret))
+}
+
+#[derive(Debug, Serialize)]
+struct RotateReq {
+    secret: ByteBuf,
+}
+
+// 新密钥先保存为待生效再下发, 设备不论是否保存成功, 新旧密钥都可登录,
+// 设备首次用新密钥登录成功后旧密钥失效
+pub async fn rotate(device_id: i64, op: &Operation<'_>) -> Result<(), AppErr> {
+    let conn = get_conn(device_id)?;
+    let secret = new_secret();
+    if !store::auth::set_pending(device_id, &secret).await? {
+        return not_found("设备未分配密钥");
+    }
+    let req = RotateReq {
+        secret: ByteBuf::from(secret),
+    };
+    exec_logged(&conn, op, "rotate_secret", "", cmd::AUTH_ROTATE, &req, ROTATE_TIMEOUT).await
+}
+
+// 自动登记时分配的密钥只在登记它的连接上下发一次, 之后的登录都需要签名,
+// 设备未收到密钥时需在网页端重新分配
+pub(super) async fn deliver(conn: &SharedConn) -> Result<(), AppErr> {
+    if !conn.info.provisioned {
+        return Ok(());
+    }
+    let secrets = match store::auth::get_secrets(conn.info.id).await? {
+        Some(secrets) if secrets.provisional => secrets,
+        _ => return Ok(()),
+    };
+    let req = RotateReq {
+        secret: ByteBuf::from(secrets.secret),
+    };
+    let _: () = conn.exec_req(cmd::AUTH_ROTATE, &req, ROTATE_TIMEOUT).await?;
+    store::auth::confirm(conn.info.id).await?;
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use super::{cmd, event::{self, kind}, profile, MaskValue, MASK_TIMEOUT};
+use crate::{
+    error::AppErr,
+    serve::{conn::SharedConn, manager},
+    store,
+};
+
+async fn push(conn: &SharedConn, mask: u32) -> Result<(), AppErr> {
+    let res: MaskValue = conn.exec_req(cmd::BILL_SET_MASK, &MaskValue { mask }, MASK_TIMEOUT).await?;
+    store::bill::set_applied_mask(conn.info.id, res.mask).await?;
+    event::publish(Some(conn.info.id), kind::BILL_MASK, &res);
+    Ok(())
+}
+
+// 设备离线时不下发, 下次登录后由 sync_mask 补发
+pub async fn push_mask(device_id: i64) -> Result<(), AppErr> {
+    if let Some(conn) = manager::find(device_id) {
+        let bill = store::bill::get(device_id).await?;
+        let mask = profile::bill_mask(device_id, bill.type_mask).await?;
+        push(&conn, mask).await?;
+    }
+    Ok(())
+}
+
+pub async fn sync_mask(conn: &SharedConn) -> Result<(), AppErr> {
+    let bill = store::bill::get(conn.info.id).await?;
+    let mask = profile::bill_mask(conn.info.id, bill.type_mask).await?;
+    if mask != bill.applied_mask {
+        push(conn, mask).await?;
+    }
+    Ok(())
+}
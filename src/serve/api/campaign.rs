@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use tokio::time;
+
+use super::firmware;
+use crate::{
+    error::{validation, AppErr, ErrorExt},
+    store::{self, campaign::{state, TableCampaign}, firmware::target},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub enum Targets<'a> {
+    Devices(&'a [i64]),
+    Version(&'a str),
+}
+
+// waves 为每一波累计发布的百分比, 最后一波必须为 100
+pub fn check_threshold(failure_threshold: u8) -> Result<(), AppErr> {
+    if failure_threshold > 100 {
+        return validation("失败率阈值无效");
+    }
+    Ok(())
+}
+
+pub async fn create(
+    name: &str,
+    firmware_id: i64,
+    targets: Targets<'_>,
+    waves: &[u8],
+    failure_threshold: u8,
+) -> Result<i64, AppErr> {
+    if waves.is_empty() || waves.windows(2).any(|w| w[0] >= w[1]) || waves[waves.len() - 1] != 100 {
+        return validation("发布批次必须递增且最后一批为100%");
+    }
+    check_threshold(failure_threshold)?;
+    let firmware = store::firmware::get(firmware_id).await?;
+    let mut ids = match targets {
+        Targets::Devices(ids) => ids.to_vec(),
+        Targets::Version(version) if firmware.target == target::APP => {
+            store::device::select_id_by_app_version(version).await?.into_vec()
+        }
+        Targets::Version(version) => store::device::select_id_by_mcu_version(version).await?.into_vec(),
+    };
+    if ids.is_empty() {
+        return validation("没有目标设备");
+    }
+    ids.shuffle(&mut rand::thread_rng());
+
+    let total = ids.len();
+    let mut devices = Vec::with_capacity(total);
+    let mut wave = 0;
+    for (index, id) in ids.into_iter().enumerate() {
+        while index >= (total * waves[wave] as usize).div_ceil(100) {
+            wave += 1;
+        }
+        devices.push((id, wave));
+    }
+
+    let id = store::campaign::create(name, firmware_id, waves, failure_threshold, &devices).await?;
+    Ok(id)
+}
+
+async fn check(campaign: &TableCampaign) -> Result<(), AppErr> {
+    let wave = campaign.current_wave;
+    let devices = store::campaign::select_unreleased(campaign.id, wave).await?;
+    for device_id in devices.iter() {
+        let update_id = firmware::deploy(*device_id, campaign.firmware_id).await?;
+        store::campaign::set_released(campaign.id, *device_id, update_id).await?;
+    }
+
+    let stats = store::campaign::wave_stats(campaign.id).await?;
+    if let Some(s) = stats.iter().find(|s| s.wave == wave) {
+        if s.failed * 100 > campaign.failure_threshold * s.total {
+            let msg = format!("第{}批失败率超过{}%", wave + 1, campaign.failure_threshold);
+            store::campaign::set_state(campaign.id, state::RUNNING, state::PAUSED, Some(&msg)).await?;
+            return Ok(());
+        }
+        if s.succeeded + s.failed + s.cancelled < s.total {
+            return Ok(());
+        }
+    }
+
+    if (wave + 1) as usize >= campaign.waves.len() {
+        store::campaign::set_state(campaign.id, state::RUNNING, state::FINISHED, None).await?;
+    } else {
+        store::campaign::set_current_wave(campaign.id, wave + 1).await?;
+    }
+    Ok(())
+}
+
+pub async fn run() {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let campaigns = match store::campaign::select_by_state(state::RUNNING).await {
+            Ok(v) => v,
+            Err(e) => {
+                println!("campaign err:{}", e);
+                continue;
+            }
+        };
+        for campaign in campaigns.iter() {
+            check(campaign).await.print_if_err();
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::cmd;
+use crate::{
+    config::{DEVICE_TIMEZONE, DEVICE_UTC_OFFSET_MINUTES},
+    error::AppErr,
+    serve::{conn::SharedConn, frame::{recv::RequestFrame, Body, ToFrameBody}},
+    store,
+    utils::current_timestamp_millis,
+};
+
+const TIME_SET_TIMEOUT: Duration = Duration::from_secs(5);
+// 上报的偏差与本次请求单程估算值相差超过此值时视为无效, 不保存
+const OFFSET_TOLERANCE_MS: i64 = 5000;
+
+#[derive(Debug, Deserialize)]
+struct SyncReq {
+    device_time: i64,
+    // 设备根据上一次同步计算出的时钟偏差 (设备 - 服务器)
+    clock_offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SyncRes {
+    device_time: i64,
+    recv_time: i64,
+    send_time: i64,
+    timezone: &'static str,
+    utc_offset: i32,
+}
+
+// 设备按 ((device_time - recv_time) + (本地收到时间 - send_time)) / 2 计算偏差 (设备 - 服务器)
+pub(super) async fn on_sync(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let recv_time = current_timestamp_millis();
+    let req: SyncReq = frame.parse()?;
+    if let Some(offset) = req.clock_offset {
+        // device_time - recv_time 为偏差减去上行延迟, 符号算反时相差约两倍偏差
+        let estimate = req.device_time - recv_time;
+        if (offset - estimate).abs() <= OFFSET_TOLERANCE_MS {
+            store::device::set_clock_offset(conn.info.id, offset).await?;
+        } else {
+            println!("device:{} clock_offset:{} estimate:{} ignored", conn.info.id, offset, estimate);
+        }
+    }
+    let res = SyncRes {
+        device_time: req.device_time,
+        recv_time,
+        send_time: current_timestamp_millis(),
+        timezone: DEVICE_TIMEZONE,
+        utc_offset: DEVICE_UTC_OFFSET_MINUTES,
+    };
+    res.to_res()
+}
+
+#[derive(Debug, Serialize)]
+struct SetTimeReq {
+    server_time: i64,
+    timezone: &'static str,
+    utc_offset: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetTimeRes {
+    // 设备收到请求时 (校时前) 的本地时间
+    device_time: i64,
+}
+
+// 按记录的时钟偏差把设备上报的时间 (秒) 换算为服务器时间
+pub async fn to_server_timestamp(device_id: i64, device_timestamp: i64) -> Result<i64, AppErr> {
+    let device = store::device::get(device_id).await?;
+    Ok(device_timestamp - device.clock_offset / 1000)
+}
+
+pub async fn push_time(conn: &SharedConn) -> Result<(), AppErr> {
+    let start = current_timestamp_millis();
+    let req = SetTimeReq {
+        server_time: start,
+        timezone: DEVICE_TIMEZONE,
+        utc_offset: DEVICE_UTC_OFFSET_MINUTES,
+    };
+    let res: SetTimeRes = conn.exec_req(cmd::TIME_SET, &req, TIME_SET_TIMEOUT).await?;
+    let end = current_timestamp_millis();
+    let offset = res.device_time - (start + end) / 2;
+    store::device::set_clock_offset(conn.info.id, offset).await?;
+    Ok(())
+}
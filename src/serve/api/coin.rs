@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{cmd, event::{self, kind}, get_conn, profile, MaskValue, MASK_TIMEOUT};
+use crate::{
+    error::{code_errs, conflict, validation, AppErr},
+    serve::{conn::SharedConn, frame::{recv::RequestFrame, Body, ToFrameBody}, manager},
+    store::{self, coin::TableCoinInfo, payout::{self, state, PayoutCount, TablePayout}},
+    utils::Array,
+};
+
+const PAYOUT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InfoReport {
+    // 0: 定时上报 1: 数量变化
+    reason: u8,
+    infos: Array<TableCoinInfo>,
+}
+
+// 设备定时或储币管数量变化时上报
+pub(super) async fn on_info(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let report: InfoReport = frame.parse()?;
+    store::coin::update_info(conn.info.id, report.reason, &report.infos).await?;
+    event::publish(Some(conn.info.id), kind::COIN_INFO, &report);
+    ().to_res()
+}
+
+async fn push(conn: &SharedConn, mask: u32) -> Result<(), AppErr> {
+    let res: MaskValue = conn.exec_req(cmd::COIN_SET_MASK, &MaskValue { mask }, MASK_TIMEOUT).await?;
+    store::coin::set_applied_mask(conn.info.id, res.mask).await?;
+    event::publish(Some(conn.info.id), kind::COIN_MASK, &res);
+    Ok(())
+}
+
+// 设备离线时不下发, 下次登录后由 sync_mask 补发
+pub async fn push_mask(device_id: i64) -> Result<(), AppErr> {
+    if let Some(conn) = manager::find(device_id) {
+        let coin = store::coin::get(device_id).await?;
+        let mask = profile::coin_mask(device_id, coin.type_mask).await?;
+        push(&conn, mask).await?;
+    }
+    Ok(())
+}
+
+pub async fn sync_mask(conn: &SharedConn) -> Result<(), AppErr> {
+    let coin = store::coin::get(conn.info.id).await?;
+    let mask = profile::coin_mask(conn.info.id, coin.type_mask).await?;
+    if mask != coin.applied_mask {
+        push(conn, mask).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct PayoutReq<'a> {
+    amount: Option<u32>,
+    counts: Option<&'a [PayoutCount]>,
+}
+
+#[derive(Debug, Serialize)]
+struct PayoutEvent<'a> {
+    request_key: &'a str,
+    res: Option<&'a PayoutRes>,
+    err_msg: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PayoutRes {
+    pub amount: u32,
+    pub counts: Array<PayoutCount>,
+}
+
+pub async fn payout(
+    device_id: i64,
+    request_key: &str,
+    amount: Option<u32>,
+    counts: Option<&[PayoutCount]>,
+) -> Result<PayoutRes, AppErr> {
+    if amount.is_some() == counts.is_some() {
+        return validation("amount和counts必须二选一");
+    }
+    // 已有记录时直接返回结果, 设备离线也不影响重试
+    if let Some(record) = payout::get_by_key(request_key).await? {
+        return replay(device_id, amount, counts, record);
+    }
+    let conn = get_conn(device_id)?;
+
+    let id = match payout::begin(device_id, request_key, amount, counts).await? {
+        Some(id) => id,
+        None => return conflict("出币正在进行中"),
+    };
+
+    let req = PayoutReq { amount, counts };
+    let ret: Result<PayoutRes, AppErr> = conn.exec_req(cmd::COIN_PAYOUT, &req, PAYOUT_TIMEOUT).await;
+    match &ret {
+        Ok(res) => payout::set_succ(id, res.amount, &res.counts).await?,
+        Err(e) => {
+            println!("payout device:{} key:{} err:{}", device_id, request_key, e);
+            let info = e.to_info();
+            payout::set_fail(id, info.err_code, &info.err_msg).await?;
+        }
+    };
+    let result = PayoutEvent {
+        request_key,
+        res: ret.as_ref().ok(),
+        err_msg: ret.as_ref().err().map(|e| e.to_string()),
+    };
+    event::publish(Some(device_id), kind::COIN_PAYOUT, &result);
+    ret
+}
+
+fn replay(
+    device_id: i64,
+    amount: Option<u32>,
+    counts: Option<&[PayoutCount]>,
+    record: TablePayout,
+) -> Result<PayoutRes, AppErr> {
+    if record.device_id != device_id {
+        return conflict("请求标识已被其他设备使用");
+    }
+    if record.req_amount != amount || record.req_counts.as_deref() != counts {
+        return conflict("请求标识已用于不同的出币参数");
+    }
+    match record.state {
+        state::SUCC => Ok(PayoutRes {
+            amount: record.amount,
+            counts: record.counts.unwrap_or_default(),
+        }),
+        state::PENDING => conflict("出币正在进行中"),
+        // 按首次失败的错误码返回
+        _ => code_errs(record.err_code, record.err_msg.unwrap_or_default()),
+    }
+}
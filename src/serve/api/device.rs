@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_cbor::Value;
+
+use super::{cmd, exec_logged, get_conn, ConnState, Operation};
+use crate::{
+    error::{not_found, validation, AppErr},
+    serve::manager,
+    store::{self, diagnostic::{item, SelfTestReport}},
+};
+
+pub mod reboot_target {
+    pub const APP: u8 = 0;
+    pub const MCU: u8 = 1;
+    pub const COIN: u8 = 2;
+    pub const BILL: u8 = 3;
+}
+
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(10);
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+const RPC_MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Serialize)]
+struct RebootReq {
+    target: u8,
+}
+
+// 设备应答后再执行重启/复位
+pub async fn reboot(device_id: i64, target: u8, op: &Operation<'_>) -> Result<(), AppErr> {
+    let name = match target {
+        reboot_target::APP => "app",
+        reboot_target::MCU => "mcu",
+        reboot_target::COIN => "coin",
+        reboot_target::BILL => "bill",
+        _ => return validation("无效的重启目标"),
+    };
+    let conn = get_conn(device_id)?;
+    exec_logged(&conn, op, "reboot", name, cmd::REBOOT, &RebootReq { target }, REBOOT_TIMEOUT).await
+}
+
+#[derive(Debug, Serialize)]
+struct SelfTestReq {
+    items: u8,
+}
+
+// 失败时同样保存记录, 返回记录id
+pub async fn self_test(device_id: i64, items: u8, op: &Operation<'_>) -> Result<i64, AppErr> {
+    if items == 0 || items & !(item::COIN | item::BILL | item::MDB) != 0 {
+        return validation("无效的自检项目");
+    }
+    let conn = get_conn(device_id)?;
+    let args = format!("items={}", items);
+    let ret: Result<SelfTestReport, AppErr> =
+        exec_logged(&conn, op, "self_test", &args, cmd::SELF_TEST, &SelfTestReq { items }, SELF_TEST_TIMEOUT).await;
+    let err_msg = ret.as_ref().err().map(|e| e.to_string());
+    let report = match &ret {
+        Ok(report) => Ok(report),
+        Err(_) => Err(err_msg.as_deref().unwrap_or_default()),
+    };
+    let id = store::diagnostic::create(device_id, items, op.operator, report).await?;
+    Ok(id)
+}
+
+// 允许透传的服务端到设备的查询和诊断命令, 其余命令必须走对应接口
+// 设备主动上报的命令(COIN_INFO, TELEMETRY 等)由设备发起, 不能反向透传
+const RPC_ALLOWED: [u8; 2] = [
+    // 只让设备开始上传日志, 上传走 LOG_BEGIN/LOG_CHUNK/LOG_END 的正常流程, 不改设备状态
+    cmd::LOG_REQUEST,
+    // 只运行自检并返回结果, 不修改配置, 也不会出币或重启
+    cmd::SELF_TEST,
+];
+
+pub async fn rpc(
+    device_id: i64,
+    cmd: u8,
+    body: &Value,
+    timeout: Duration,
+    op: &Operation<'_>,
+) -> Result<Value, AppErr> {
+    if !RPC_ALLOWED.contains(&cmd) {
+        return validation("该命令不允许透传");
+    }
+    if timeout.is_zero() || timeout > RPC_MAX_TIMEOUT {
+        return validation("超时时间必须在0到120秒之间");
+    }
+    let conn = get_conn(device_id)?;
+    let args = format!("cmd=0x{:02X}", cmd);
+    exec_logged(&conn, op, "rpc", &args, cmd, body, timeout).await
+}
+
+pub fn online_ids() -> Vec<i64> {
+    manager::device_ids()
+}
+
+pub fn connections(device_id: Option<i64>, addr: Option<&str>) -> Vec<ConnState> {
+    manager::conn_states(device_id, addr)
+}
+
+pub fn kick(session_id: u64) -> Result<(), AppErr> {
+    if !manager::kick(session_id) {
+        return not_found("连接不存在");
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::{sync::OnceLock, time::Duration};
+
+use dashmap::DashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{cmd, get_conn};
+use crate::{
+    config::{
+        LOG_CHUNK_SIZE, LOG_MAX_COUNT, LOG_MAX_DAYS, LOG_MAX_SIZE, LOG_MAX_UPLOADING,
+        LOG_UPLOAD_EXPIRE_SECS,
+    },
+    error::{code_err, conflict, err_code, proto_err, AppErr},
+    serve::{conn::SharedConn, frame::{recv::RequestFrame, Body, ToFrameBody}},
+    store::{self, device_log::{state, TableDeviceLog}, file},
+    utils::current_timestamp,
+};
+
+const LOG_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct LogRequest {
+    since_timestamp: i64,
+}
+
+// 设备应答后通过 LOG_BEGIN/LOG_CHUNK/LOG_END 上传
+pub async fn request_upload(device_id: i64, since_timestamp: i64) -> Result<(), AppErr> {
+    let conn = get_conn(device_id)?;
+    conn.exec_req(cmd::LOG_REQUEST, &LogRequest { since_timestamp }, LOG_REQUEST_TIMEOUT)
+        .await
+}
+
+// 正在写入时拒绝删除
+pub async fn remove(id: i64) -> Result<(), AppErr> {
+    let _guard = match try_lock(id) {
+        Some(guard) => guard,
+        None => return conflict("日志正在写入"),
+    };
+    remove_locked(id).await
+}
+
+async fn remove_locked(id: i64) -> Result<(), AppErr> {
+    let log = store::device_log::get(id).await?;
+    file::remove(&file::log_path(log.device_id, id)).await?;
+    store::device_log::delete(id).await?;
+    Ok(())
+}
+
+// 正在写入的日志留到下次清理
+async fn prune(device_id: i64) -> Result<(), AppErr> {
+    let now = current_timestamp();
+    let before = now - LOG_MAX_DAYS * 24 * 3600;
+    let stale_before = now - LOG_UPLOAD_EXPIRE_SECS;
+    let ids = store::device_log::select_expired(device_id, LOG_MAX_COUNT, before, stale_before).await?;
+    for id in ids.iter() {
+        if let Some(_guard) = try_lock(*id) {
+            remove_locked(*id).await?;
+        }
+    }
+    Ok(())
+}
+
+// 正在写入的日志, 重传的分块可能在不同任务中同时到达
+fn writing() -> &'static DashSet<i64> {
+    static SET: OnceLock<DashSet<i64>> = OnceLock::new();
+    SET.get_or_init(DashSet::new)
+}
+
+struct WriteGuard(i64);
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        writing().remove(&self.0);
+    }
+}
+
+fn try_lock(log_id: i64) -> Option<WriteGuard> {
+    writing().insert(log_id).then_some(WriteGuard(log_id))
+}
+
+async fn get_uploading(conn: &SharedConn, log_id: i64) -> Result<TableDeviceLog, AppErr> {
+    let log = store::device_log::get(log_id).await?;
+    if log.device_id != conn.info.id {
+        return proto_err("log not found");
+    }
+    if log.state != state::UPLOADING {
+        return proto_err("log upload finished");
+    }
+    Ok(log)
+}
+
+#[derive(Debug, Deserialize)]
+struct BeginReq {
+    name: String,
+    since_timestamp: i64,
+    size: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct BeginRes {
+    log_id: i64,
+    chunk_size: usize,
+}
+
+pub(super) async fn on_begin(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let req: BeginReq = frame.parse()?;
+    if req.size < 0 {
+        return proto_err("log size invalid");
+    }
+    if req.size > LOG_MAX_SIZE {
+        return code_err(err_code::PAYLOAD_TOO_LARGE, "日志超过大小上限");
+    }
+    prune(conn.info.id).await?;
+    let created =
+        store::device_log::create(conn.info.id, &req.name, req.since_timestamp, req.size, LOG_MAX_UPLOADING).await?;
+    let log_id = match created {
+        Some(log_id) => log_id,
+        None => return conflict("正在上传的日志过多"),
+    };
+    file::create(&file::log_path(conn.info.id, log_id)).await?;
+    BeginRes {
+        log_id,
+        chunk_size: LOG_CHUNK_SIZE,
+    }
+    .to_res()
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkReq {
+    log_id: i64,
+    offset: i64,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkRes {
+    received: i64,
+}
+
+// offset 与已接收长度不一致或正在写入其他分块时忽略数据, 设备按返回的 received 续传
+pub(super) async fn on_chunk(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let req: ChunkReq = frame.parse()?;
+    let guard = try_lock(req.log_id);
+    let log = get_uploading(conn, req.log_id).await?;
+    if guard.is_none() {
+        return ChunkRes { received: log.received }.to_res();
+    }
+    let mut received = log.received;
+    if req.offset == received && received + req.data.len() as i64 <= log.size {
+        file::append(&file::log_path(log.device_id, log.id), &req.data).await?;
+        received += req.data.len() as i64;
+        store::device_log::set_received(log.id, received).await?;
+    }
+    ChunkRes { received }.to_res()
+}
+
+#[derive(Debug, Deserialize)]
+struct EndReq {
+    log_id: i64,
+}
+
+pub(super) async fn on_end(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let req: EndReq = frame.parse()?;
+    let _guard = match try_lock(req.log_id) {
+        Some(guard) => guard,
+        None => return conflict("日志正在写入"),
+    };
+    let log = get_uploading(conn, req.log_id).await?;
+    if log.received != log.size {
+        return conflict("日志数据不完整");
+    }
+    store::device_log::set_complete(log.id).await?;
+    prune(log.device_id).await?;
+    ().to_res()
+}
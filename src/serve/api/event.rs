@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    config::{EVENT_CHANNEL_SIZE, EVENT_REPLAY_SIZE},
+    utils::current_timestamp,
+};
+
+pub mod kind {
+    pub const ONLINE: &str = "device.online";
+    pub const OFFLINE: &str = "device.offline";
+    pub const LOGIN: &str = "device.login";
+    pub const COIN_INFO: &str = "coin.info";
+    pub const COIN_PAYOUT: &str = "coin.payout";
+    pub const COIN_MASK: &str = "coin.mask";
+    pub const BILL_MASK: &str = "bill.mask";
+    pub const COMMAND: &str = "command.result";
+    // 登录被拒绝, device_id 可能为空
+    pub const ALERT_LOGIN: &str = "alert.login";
+    pub const ALERT_FIRMWARE: &str = "alert.firmware";
+}
+
+#[derive(Debug, Serialize)]
+pub struct Event {
+    // 单调递增, 断线重连时用于补发
+    pub id: u64,
+    pub device_id: Option<i64>,
+    pub kind: &'static str,
+    pub timestamp: i64,
+    pub data: Value,
+}
+
+pub type SharedEvent = Arc<Event>;
+
+struct Hub {
+    next_id: u64,
+    replay: VecDeque<SharedEvent>,
+}
+
+struct EventBus {
+    hub: Mutex<Hub>,
+    sender: broadcast::Sender<SharedEvent>,
+}
+
+static BUS: OnceLock<EventBus> = OnceLock::new();
+
+fn get_bus() -> &'static EventBus {
+    BUS.get_or_init(|| EventBus {
+        hub: Mutex::new(Hub {
+            next_id: 1,
+            replay: VecDeque::with_capacity(EVENT_REPLAY_SIZE),
+        }),
+        sender: broadcast::channel(EVENT_CHANNEL_SIZE).0,
+    })
+}
+
+// 没有订阅者时只写入补发缓存
+pub fn publish<T: Serialize>(device_id: Option<i64>, kind: &'static str, data: &T) {
+    let data = serde_json::to_value(data).unwrap_or(Value::Null);
+    let bus = get_bus();
+    let mut hub = bus.hub.lock().unwrap();
+    let event = Arc::new(Event {
+        id: hub.next_id,
+        device_id,
+        kind,
+        timestamp: current_timestamp(),
+        data,
+    });
+    hub.next_id += 1;
+    if hub.replay.len() == EVENT_REPLAY_SIZE {
+        hub.replay.pop_front();
+    }
+    hub.replay.push_back(event.clone());
+    _ = bus.sender.send(event);
+}
+
+pub struct Subscription {
+    // after_id 之后还在缓存中的事件
+    pub replay: Vec<SharedEvent>,
+    pub receiver: broadcast::Receiver<SharedEvent>,
+    // 订阅时最新的事件 id
+    pub last_id: u64,
+}
+
+// 在同一把锁内取补发数据和订阅, 两者之间不会漏掉事件
+pub fn subscribe(after_id: Option<u64>) -> Subscription {
+    let bus = get_bus();
+    let hub = bus.hub.lock().unwrap();
+    let replay = match after_id {
+        Some(id) => hub.replay.iter().filter(|e| e.id > id).cloned().collect(),
+        None => Vec::new(),
+    };
+    Subscription {
+        replay,
+        receiver: bus.sender.subscribe(),
+        last_id: hub.next_id - 1,
+    }
+}
+
+// 订阅者处理过慢被跳过时, 从补发缓存中取回
+pub fn since(after_id: u64) -> Vec<SharedEvent> {
+    let hub = get_bus().hub.lock().unwrap();
+    hub.replay.iter().filter(|e| e.id > after_id).cloned().collect()
+}
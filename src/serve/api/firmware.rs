@@ -0,0 +1,200 @@
+use std::{sync::OnceLock, time::Duration};
+
+use dashmap::DashSet;
+use serde::{Deserialize, Serialize};
+
+use super::{cmd, event::{self, kind}};
+use crate::{
+    config::FIRMWARE_CHUNK_SIZE,
+    error::{proto_err, AppErr, ErrorExt},
+    serve::{conn::SharedConn, frame::{recv::RequestFrame, Body, ToFrameBody}, manager},
+    store::{self, file, firmware::{state, target, TableFirmwareUpdate}},
+};
+
+const FIRMWARE_TIMEOUT: Duration = Duration::from_secs(10);
+// 设备收到 FIRMWARE_END 后校验完整性
+const FIRMWARE_VERIFY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 正在下发固件的连接 session_id, 设备重连后旧连接的下发循环不影响新连接
+fn delivering() -> &'static DashSet<u64> {
+    static SET: OnceLock<DashSet<u64>> = OnceLock::new();
+    SET.get_or_init(DashSet::new)
+}
+
+#[derive(Debug, Serialize)]
+struct BeginReq<'a> {
+    update_id: i64,
+    target: i32,
+    version: &'a str,
+    size: i64,
+    hash: &'a str,
+    chunk_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct BeginRes {
+    // 设备已接收的长度, 用于断线后续传
+    offset: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkReq<'a> {
+    update_id: i64,
+    offset: i64,
+    #[serde(with = "serde_bytes")]
+    data: &'a [u8],
+}
+
+#[derive(Debug, Serialize)]
+struct EndReq {
+    update_id: i64,
+}
+
+pub async fn deploy(device_id: i64, firmware_id: i64) -> Result<i64, AppErr> {
+    store::device::get(device_id).await?;
+    let firmware = store::firmware::get(firmware_id).await?;
+    let id = store::firmware::create_update(device_id, &firmware).await?;
+    if let Some(conn) = manager::find(device_id) {
+        start(conn);
+    }
+    Ok(id)
+}
+
+pub fn start(conn: SharedConn) {
+    if delivering().insert(conn.info.session_id) {
+        tokio::spawn(deliver_all(conn));
+    }
+}
+
+async fn next_update(device_id: i64) -> Result<Option<TableFirmwareUpdate>, AppErr> {
+    for s in [state::DOWNLOADING, state::PENDING] {
+        let updates = store::firmware::select_update_by_state(device_id, s).await?;
+        if let Some(update) = updates.into_vec().into_iter().next() {
+            return Ok(Some(update));
+        }
+    }
+    Ok(None)
+}
+
+async fn is_active(update_id: i64) -> Result<bool, AppErr> {
+    let update = store::firmware::get_update(update_id).await?;
+    Ok(update.state == state::DOWNLOADING)
+}
+
+async fn deliver(conn: &SharedConn, update: &TableFirmwareUpdate) -> Result<(), AppErr> {
+    let firmware = store::firmware::get(update.firmware_id).await?;
+    let data = file::read(&file::firmware_path(&firmware.hash)).await?;
+    if !store::firmware::start_download(update.id).await? {
+        return Ok(());
+    }
+
+    let req = BeginReq {
+        update_id: update.id,
+        target: firmware.target,
+        version: &firmware.version,
+        size: firmware.size,
+        hash: &firmware.hash,
+        chunk_size: FIRMWARE_CHUNK_SIZE,
+    };
+    let res: BeginRes = conn.exec_req(cmd::FIRMWARE_BEGIN, &req, FIRMWARE_TIMEOUT).await?;
+
+    let mut offset = res.offset.clamp(0, firmware.size) as usize;
+    while offset < data.len() {
+        if !is_active(update.id).await? {
+            return Ok(());
+        }
+        let end = data.len().min(offset + FIRMWARE_CHUNK_SIZE);
+        let req = ChunkReq {
+            update_id: update.id,
+            offset: offset as i64,
+            data: &data[offset..end],
+        };
+        conn.exec_req::<_, ()>(cmd::FIRMWARE_CHUNK, &req, FIRMWARE_TIMEOUT).await?;
+        offset = end;
+        store::firmware::set_update_offset(update.id, offset as i64).await?;
+    }
+
+    let req = EndReq { update_id: update.id };
+    conn.exec_req::<_, ()>(cmd::FIRMWARE_END, &req, FIRMWARE_VERIFY_TIMEOUT).await?;
+    store::firmware::finish_download(update.id).await?;
+    Ok(())
+}
+
+// 依次下发等待中的升级, 全部完成返回 true, 出错中断返回 false
+// 设备返回的错误视为升级失败, 连接错误则保留进度等待重新登录后续传
+async fn deliver_pending(conn: &SharedConn) -> bool {
+    let device_id = conn.info.id;
+    loop {
+        let update = match next_update(device_id).await {
+            Ok(Some(update)) => update,
+            Ok(None) => return true,
+            Err(e) => {
+                println!("firmware device:{} err:{}", device_id, e);
+                return false;
+            }
+        };
+        match deliver(conn, &update).await {
+            Ok(()) => {}
+            Err(AppErr::Custom(info)) => {
+                store::firmware::set_update_state(update.id, state::FAILED, Some(&info.err_msg))
+                    .await
+                    .print_if_err();
+            }
+            Err(e) => {
+                println!("firmware device:{} update:{} err:{}", device_id, update.id, e);
+                return false;
+            }
+        }
+    }
+}
+
+async fn deliver_all(conn: SharedConn) {
+    let session_id = conn.info.session_id;
+    loop {
+        let drained = deliver_pending(&conn).await;
+        delivering().remove(&session_id);
+        if !drained {
+            break;
+        }
+        // deploy 可能在最后一次查询之后、清除标记之前写入, 清除后再查一次
+        match next_update(conn.info.id).await {
+            Ok(Some(_)) if delivering().insert(session_id) => continue,
+            _ => break,
+        }
+    }
+}
+
+// 登录时上报的版本与安装中的固件版本一致则升级成功
+pub async fn confirm(device_id: i64, app_version: &str, mcu_version: Option<&str>) -> Result<(), AppErr> {
+    let updates = store::firmware::select_update_by_state(device_id, state::INSTALLING).await?;
+    for update in updates.iter() {
+        let reported = match update.target {
+            target::APP => Some(app_version),
+            _ => mcu_version,
+        };
+        if reported == Some(update.version.as_str()) {
+            store::firmware::set_update_state(update.id, state::SUCCEEDED, None).await?;
+        } else {
+            store::firmware::set_update_state(update.id, state::FAILED, Some("version mismatch")).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateReport {
+    update_id: i64,
+    err_msg: String,
+}
+
+// 设备校验或安装失败时上报
+pub(super) async fn on_fail(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    let report: StateReport = frame.parse()?;
+    let update = store::firmware::get_update(report.update_id).await?;
+    if update.device_id != conn.info.id {
+        return proto_err("update not found");
+    }
+    store::firmware::set_update_state(update.id, state::FAILED, Some(&report.err_msg)).await?;
+    event::publish(Some(conn.info.id), kind::ALERT_FIRMWARE, &report);
+    ().to_res()
+}
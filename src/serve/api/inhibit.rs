@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time;
+
+use super::{cmd, exec_logged, Operation};
+use crate::{
+    error::{validation, AppErr, ErrorExt},
+    serve::{conn::SharedConn, manager},
+    store::{self, device::peripheral},
+    utils::current_timestamp,
+};
+
+const INHIBIT_TIMEOUT: Duration = Duration::from_secs(5);
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+struct InhibitReq {
+    peripheral: u8,
+    inhibit: bool,
+}
+
+fn peripheral_name(peripheral: u8) -> Result<&'static str, AppErr> {
+    match peripheral {
+        peripheral::COIN => Ok("coin"),
+        peripheral::BILL => Ok("bill"),
+        _ => validation("无效的外设"),
+    }
+}
+
+// 先保存状态, 设备离线时在下次登录后由 sync 补发
+pub async fn set(
+    device_id: i64,
+    peripheral: u8,
+    inhibit: bool,
+    until: Option<i64>,
+    op: &Operation<'_>,
+) -> Result<(), AppErr> {
+    let name = peripheral_name(peripheral)?;
+    if !inhibit && until.is_some() {
+        return validation("恢复收款时不能设置自动恢复时间");
+    }
+    if let Some(until) = until {
+        if until <= current_timestamp() {
+            return validation("自动恢复时间必须晚于当前时间");
+        }
+    }
+    store::device::set_inhibit(device_id, peripheral, inhibit, until).await?;
+
+    if let Some(conn) = manager::find(device_id) {
+        let kind = if inhibit { "inhibit" } else { "enable" };
+        let args = match until {
+            Some(until) => format!("{} until={}", name, until),
+            None => name.to_string(),
+        };
+        let req = InhibitReq { peripheral, inhibit };
+        let _: () = exec_logged(&conn, op, kind, &args, cmd::INHIBIT, &req, INHIBIT_TIMEOUT).await?;
+    }
+    Ok(())
+}
+
+pub async fn sync(conn: &SharedConn) -> Result<(), AppErr> {
+    let device = store::device::get(conn.info.id).await?;
+    for (peripheral, inhibit) in [
+        (peripheral::COIN, device.coin_inhibit),
+        (peripheral::BILL, device.bill_inhibit),
+    ] {
+        let req = InhibitReq { peripheral, inhibit };
+        let _: () = conn.exec_req(cmd::INHIBIT, &req, INHIBIT_TIMEOUT).await?;
+    }
+    Ok(())
+}
+
+async fn check() -> Result<(), AppErr> {
+    let op = Operation {
+        operator: "system",
+        reason: "到达自动恢复时间",
+    };
+    let now = current_timestamp();
+    for peripheral in [peripheral::COIN, peripheral::BILL] {
+        let ids = store::device::select_inhibit_expired(peripheral, now).await?;
+        for id in ids.iter() {
+            set(*id, peripheral, false, None, &op).await.print_if_err();
+        }
+    }
+    Ok(())
+}
+
+pub async fn run() {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        check().await.print_if_err();
+    }
+}
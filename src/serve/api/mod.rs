@@ -1,22 +1,70 @@
-use std::{time::Duration, sync::atomic::{AtomicU32, Ordering}, net::SocketAddr};
+use std::{time::Duration, sync::atomic::{AtomicU32, AtomicU64, Ordering}, net::SocketAddr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use tokio::{net::TcpStream, time};
 
-use super::{conn::SharedConn, frame::{read, recv::RequestFrame, Body}};
+use super::{conn::SharedConn, frame::{read, recv::RequestFrame, Body}, manager};
 use crate::{
-    error::{proto_err, AppErr, ErrorExt},
+    error::{code_err, code_errs, err_code, proto_err, unauthorized, AppErr, ErrorExt},
     store,
     serve::frame::{write, send::{SendFrame, ResponseFrame}, BaseFrame},
+    utils::current_timestamp,
 };
 
-const CMD_LOGIN: u8 = 0x01;
+pub mod auth;
+pub mod bill;
+pub mod campaign;
+pub mod clock;
+pub mod coin;
+pub mod device;
+pub mod device_log;
+pub mod event;
+pub mod firmware;
+pub mod inhibit;
+pub mod profile;
+pub mod provision;
+pub mod setting;
+pub mod telemetry;
+
+mod cmd {
+    pub const LOGIN: u8 = 0x01;
+    pub const COIN_PAYOUT: u8 = 0x02;
+    pub const COIN_SET_MASK: u8 = 0x03;
+    pub const BILL_SET_MASK: u8 = 0x04;
+    pub const COIN_INFO: u8 = 0x05;
+    pub const REBOOT: u8 = 0x06;
+    pub const TIME_SYNC: u8 = 0x07;
+    pub const TIME_SET: u8 = 0x08;
+    pub const LOG_REQUEST: u8 = 0x09;
+    pub const LOG_BEGIN: u8 = 0x0A;
+    pub const LOG_CHUNK: u8 = 0x0B;
+    pub const LOG_END: u8 = 0x0C;
+    pub const FIRMWARE_BEGIN: u8 = 0x0D;
+    pub const FIRMWARE_CHUNK: u8 = 0x0E;
+    pub const FIRMWARE_END: u8 = 0x0F;
+    pub const FIRMWARE_FAIL: u8 = 0x10;
+    pub const SETTING_PUSH: u8 = 0x11;
+    pub const TELEMETRY: u8 = 0x12;
+    pub const SELF_TEST: u8 = 0x13;
+    pub const INHIBIT: u8 = 0x14;
+    pub const AUTH_CHALLENGE: u8 = 0x15;
+    pub const AUTH_ROTATE: u8 = 0x16;
+}
+
+const MASK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MaskValue {
+    mask: u32,
+}
 
 #[derive(Debug, Deserialize)]
 struct CoinInfo {
     model: String,
     version: String,
     serial_number: String,
+    type_mask: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,6 +72,7 @@ struct BillInfo {
     model: String,
     version: String,
     serial_number: String,
+    type_mask: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,15 +82,42 @@ struct LoginReq {
     mcu_version: Option<String>,
     coin_info: Option<CoinInfo>,
     bill_info: Option<BillInfo>,
+    settings_hash: Option<String>,
+    // HMAC-SHA256(secret, nonce || mac_addr)
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+}
+
+
+#[derive(Debug, Serialize)]
+pub struct ConnState {
+    pub session_id: u64,
+    pub device_id: i64,
+    pub mac_addr: String,
+    pub addr: String,
+    pub connected_since: i64,
+    pub ping_count: u32,
+    pub rtt_ms: u32,
+    // 等待写出的帧数
+    pub queue_depth: usize,
+    pub pending_calls: usize,
 }
 
+static SESSION_SEQ: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug, Serialize)]
 pub struct ConnInfo {
+    // 同一设备重连时用于区分新旧连接
+    pub session_id: u64,
     pub addr: SocketAddr,
     pub mac_addr: String,
     pub id: i64,
+    pub connected_since: i64,
     pub ping_count: AtomicU32,
+    // 最近一次请求到收到ack的时间, 毫秒
+    pub rtt_ms: AtomicU32,
+    // 本次登录时自动登记, 登录后下发分配的密钥
+    pub provisioned: bool,
 }
 
 impl ConnInfo {
@@ -50,40 +126,164 @@ impl ConnInfo {
         self.ping_count.fetch_add(1, Ordering::SeqCst);
     }
 
-
+    pub fn set_rtt(&self, rtt: Duration) {
+        self.rtt_ms.store(rtt.as_millis() as u32, Ordering::SeqCst);
+    }
 }
 
-pub async fn wait_login(stream: &mut TcpStream, addr: SocketAddr) -> Result<ConnInfo, AppErr> {
+async fn read_req(stream: &mut TcpStream, expect: u8) -> Result<RequestFrame, AppErr> {
     let frame = time::timeout(Duration::from_secs(10), read(stream)).await.wrap()?.wrap()?;
     let req_frame = frame.req()?;
-    let seq = req_frame.seq;
-    let cmd = req_frame.cmd();
-    if cmd != CMD_LOGIN {
-        return proto_err("not login");
+    if req_frame.cmd() != expect {
+        return proto_err("unexpected cmd");
     }
-    let req: LoginReq = req_frame.parse()?;
+    write(stream, &SendFrame::Ack(BaseFrame{ seq: req_frame.seq })).await?;
+    Ok(req_frame)
+}
+
+#[derive(Debug, Serialize)]
+struct LoginEvent<'a> {
+    addr: String,
+    mac_addr: &'a str,
+    app_version: &'a str,
+    mcu_version: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct LoginAlert<'a> {
+    addr: String,
+    mac_addr: &'a str,
+    err_code: i32,
+    err_msg: String,
+}
+
+fn login_alert(addr: &SocketAddr, mac_addr: &str, err: &AppErr) {
+    let info = err.to_info();
+    let alert = LoginAlert {
+        addr: addr.to_string(),
+        mac_addr,
+        err_code: info.err_code,
+        err_msg: info.err_msg,
+    };
+    event::publish(None, event::kind::ALERT_LOGIN, &alert);
+}
+
+// 把错误回复给设备后再断开
+async fn reject<T>(stream: &mut TcpStream, seq: u8, cmd: u8, err: AppErr) -> Result<T, AppErr> {
+    let info = err.to_info();
+    write(stream, &SendFrame::Res(ResponseFrame::new::<()>(seq, cmd, Err(err)))).await?;
+    code_errs(info.err_code, info.err_msg)
+}
 
-    write(stream, &SendFrame::Ack(BaseFrame{ seq })).await?;
-    
-    let id = login(&req).await?;
+// 先下发随机数, 设备用密钥签名后再登录
+pub async fn wait_login(stream: &mut TcpStream, addr: SocketAddr) -> Result<ConnInfo, AppErr> {
+    let req_frame = read_req(stream, cmd::AUTH_CHALLENGE).await?;
+    let seq = req_frame.seq;
+    let challenge: auth::ChallengeReq = req_frame.parse()?;
+    let nonce = match auth::challenge(&addr, &challenge.mac_addr) {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            login_alert(&addr, &challenge.mac_addr, &e);
+            return reject(stream, seq, cmd::AUTH_CHALLENGE, e).await;
+        }
+    };
+    let res = auth::ChallengeRes { nonce: ByteBuf::from(nonce.clone()) };
+    write(stream, &SendFrame::Res(ResponseFrame::new(seq, cmd::AUTH_CHALLENGE, Ok(res)))).await?;
 
-    write(stream, &SendFrame::Res(ResponseFrame::new(seq, cmd, Ok(id)))).await?;
+    let req_frame = read_req(stream, cmd::LOGIN).await?;
+    let seq = req_frame.seq;
+    let req: LoginReq = req_frame.parse()?;
+
+    let ret = async {
+        if req.mac_addr != challenge.mac_addr {
+            return unauthorized("mac_addr与认证请求不一致");
+        }
+        let (id, provisioned) = auth::verify(&addr, &req.mac_addr, &nonce, &req.signature).await?;
+        login(id, &req).await?;
+        firmware::confirm(id, &req.app_version, req.mcu_version.as_deref()).await?;
+        Ok((id, provisioned))
+    }
+    .await;
+    let (id, provisioned) = match ret {
+        Ok(v) => v,
+        Err(e) => {
+            login_alert(&addr, &req.mac_addr, &e);
+            return reject(stream, seq, cmd::LOGIN, e).await;
+        }
+    };
+    write(stream, &SendFrame::Res(ResponseFrame::new(seq, cmd::LOGIN, Ok(id)))).await?;
+    let login = LoginEvent {
+        addr: addr.to_string(),
+        mac_addr: &req.mac_addr,
+        app_version: &req.app_version,
+        mcu_version: req.mcu_version.as_deref(),
+    };
+    event::publish(Some(id), event::kind::LOGIN, &login);
 
     let info = ConnInfo {
+        session_id: SESSION_SEQ.fetch_add(1, Ordering::SeqCst),
         id,
         mac_addr: req.mac_addr,
+        connected_since: current_timestamp(),
         ping_count: AtomicU32::new(0),
+        rtt_ms: AtomicU32::new(0),
         addr,
+        provisioned,
     };
 
     Ok(info)
 
 }
 
-async fn login(req: &LoginReq) -> Result<i64, AppErr> {
+fn get_conn(device_id: i64) -> Result<SharedConn, AppErr> {
+    match manager::find(device_id) {
+        Some(conn) => Ok(conn),
+        None => code_err(err_code::DEVICE_OFFLINE, "设备不在线"),
+    }
+}
+
+pub struct Operation<'a> {
+    pub operator: &'a str,
+    pub reason: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandEvent<'a> {
+    log_id: i64,
+    kind: &'a str,
+    args: &'a str,
+    operator: &'a str,
+    err_msg: Option<String>,
+}
+
+// 执行远程命令并写入命令历史
+async fn exec_logged<T: Serialize, R: DeserializeOwned>(
+    conn: &SharedConn,
+    op: &Operation<'_>,
+    kind: &str,
+    args: &str,
+    cmd: u8,
+    value: &T,
+    timeout: Duration,
+) -> Result<R, AppErr> {
+    let id = store::command::begin(conn.info.id, kind, args, op.operator, op.reason).await?;
+    let ret = conn.exec_req(cmd, value, timeout).await;
+    let err_msg = ret.as_ref().err().map(|e| e.to_string());
+    store::command::finish(id, err_msg.as_deref()).await?;
+    let result = CommandEvent {
+        log_id: id,
+        kind,
+        args,
+        operator: op.operator,
+        err_msg,
+    };
+    event::publish(Some(conn.info.id), event::kind::COMMAND, &result);
+    ret
+}
+
+async fn login(id: i64, req: &LoginReq) -> Result<(), AppErr> {
     use store::*;
 
-    let id = device::create_if_not_exists(&req.mac_addr).await?;
     device::set_app_version(id, &req.app_version).await?;
 
     if let Some(mcu_version) = &req.mcu_version {
@@ -91,26 +291,59 @@ async fn login(req: &LoginReq) -> Result<i64, AppErr> {
     }
     if let Some(coin) = &req.coin_info {
         coin::update(id, &coin.model, &coin.version, &coin.serial_number).await?;
+        if let Some(mask) = coin.type_mask {
+            coin::set_applied_mask(id, mask).await?;
+        }
+    }
+    if let Some(hash) = &req.settings_hash {
+        setting::set_reported_hash(id, hash).await?;
     }
     if let Some(bill) = &req.bill_info {
         bill::update(id, &bill.model, &bill.version, &bill.serial_number).await?;
+        if let Some(mask) = bill.type_mask {
+            bill::set_applied_mask(id, mask).await?;
+        }
     }
 
-    Ok(id)
+    Ok(())
 }
 
-async fn dispatch(_frame: RequestFrame) -> Result<Body, AppErr> {
+// 登录成功后补发离线期间的配置
+pub async fn after_login(conn: SharedConn) {
+    auth::deliver(&conn).await.print_if_err();
+    clock::push_time(&conn).await.print_if_err();
+    coin::sync_mask(&conn).await.print_if_err();
+    bill::sync_mask(&conn).await.print_if_err();
+    setting::sync(&conn).await.print_if_err();
+    inhibit::sync(&conn).await.print_if_err();
+    firmware::start(conn);
+}
 
-    todo!()
+async fn dispatch(conn: &SharedConn, frame: &RequestFrame) -> Result<Body, AppErr> {
+    match frame.cmd() {
+        cmd::COIN_INFO => coin::on_info(conn, frame).await,
+        cmd::TIME_SYNC => clock::on_sync(conn, frame).await,
+        cmd::LOG_BEGIN => device_log::on_begin(conn, frame).await,
+        cmd::LOG_CHUNK => device_log::on_chunk(conn, frame).await,
+        cmd::LOG_END => device_log::on_end(conn, frame).await,
+        cmd::FIRMWARE_FAIL => firmware::on_fail(conn, frame).await,
+        _ => proto_err("invalid cmd"),
+    }
 }
 
 pub async fn handle_req(conn: SharedConn, frame: RequestFrame) {
 
     let cmd = frame.cmd();
     let seq = frame.seq;
-    let result: Result<Body, AppErr> = match cmd {
-        _ => proto_err("invalid cmd")
-    };
+    let result = dispatch(&conn, &frame).await;
     _ = conn.write(SendFrame::Res(ResponseFrame::new_body(seq, cmd, result)));
 }
 
+
+pub async fn handle_notify(conn: SharedConn, frame: RequestFrame) {
+    let ret = match frame.cmd() {
+        cmd::TELEMETRY => telemetry::on_report(&conn, &frame).await,
+        _ => proto_err("invalid cmd"),
+    };
+    ret.print_if_err();
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+
+use super::{bill, coin, setting};
+use crate::{
+    error::{AppErr, ErrorExt},
+    store::{self, setting::TableSetting},
+    utils::Array,
+};
+
+#[derive(Debug, Serialize)]
+pub struct Effective {
+    pub profile_id: Option<i64>,
+    pub coin_mask: u32,
+    pub bill_mask: u32,
+    pub settings: Array<TableSetting>,
+}
+
+// 配置方案 id 以及设备覆盖值或配置方案中的掩码, 未设置时为 None
+async fn assigned_masks(device_id: i64) -> Result<(Option<i64>, Option<u32>, Option<u32>), AppErr> {
+    let Some(d) = store::profile::get_device(device_id).await? else {
+        return Ok((None, None, None));
+    };
+    let profile = store::profile::get(d.profile_id).await?;
+    Ok((Some(profile.id), d.coin_mask.or(profile.coin_mask), d.bill_mask.or(profile.bill_mask)))
+}
+
+// 设备覆盖值 > 配置方案 > 设备自身的值
+// 生效值只用于下发, 不写回 tb_coin/tb_bill, 取消覆盖或方案后可恢复设备自身的值
+pub async fn effective(device_id: i64) -> Result<Effective, AppErr> {
+    let coin = store::coin::get(device_id).await?;
+    let bill = store::bill::get(device_id).await?;
+    let (profile_id, coin_mask, bill_mask) = assigned_masks(device_id).await?;
+
+    Ok(Effective {
+        profile_id,
+        coin_mask: coin_mask.unwrap_or(coin.type_mask),
+        bill_mask: bill_mask.unwrap_or(bill.type_mask),
+        settings: setting::expected(device_id).await?,
+    })
+}
+
+pub async fn coin_mask(device_id: i64, type_mask: u32) -> Result<u32, AppErr> {
+    let (_, mask, _) = assigned_masks(device_id).await?;
+    Ok(mask.unwrap_or(type_mask))
+}
+
+pub async fn bill_mask(device_id: i64, type_mask: u32) -> Result<u32, AppErr> {
+    let (_, _, mask) = assigned_masks(device_id).await?;
+    Ok(mask.unwrap_or(type_mask))
+}
+
+// 设备 id 和配置方案变化前的生效配置
+pub type Snapshot = Vec<(i64, Array<TableSetting>)>;
+
+// 记录生效配置, 在线设备立即下发, 离线设备登录后补发
+pub async fn apply(device_id: i64, before: &[TableSetting]) -> Result<(), AppErr> {
+    let effective = effective(device_id).await?;
+    store::setting::record_effective(device_id, before, &effective.settings).await?;
+
+    coin::push_mask(device_id).await.print_if_err();
+    bill::push_mask(device_id).await.print_if_err();
+    setting::push_changes(device_id).await.print_if_err();
+    Ok(())
+}
+
+// 修改配置方案前调用
+pub async fn snapshot(device_ids: &[i64]) -> Result<Snapshot, AppErr> {
+    let mut vec = Vec::with_capacity(device_ids.len());
+    for device_id in device_ids {
+        vec.push((*device_id, setting::expected(*device_id).await?));
+    }
+    Ok(vec)
+}
+
+pub fn propagate(snapshot: Snapshot) {
+    tokio::spawn(async move {
+        for (device_id, before) in snapshot.iter() {
+            apply(*device_id, before).await.print_if_err();
+        }
+    });
+}
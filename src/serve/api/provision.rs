@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+
+use serde::Serialize;
+
+use super::{auth, profile};
+use crate::{
+    config::{provision_auto_approve, PROVISION_PENDING_MAX},
+    error::{conflict, AppErr, ErrorExt},
+    store::{self, provision::state},
+    utils::to_hex,
+};
+
+// 未知设备登录时调用, 自动审批时返回新建的设备id
+// 其余情况按认证失败计数, 超出次数后拒绝该地址和MAC继续尝试
+pub(super) async fn on_unknown(addr: &SocketAddr, mac_addr: &str) -> Result<i64, AppErr> {
+    if provision_auto_approve() {
+        let secret = auth::new_secret();
+        return match store::provision::auto_approve(mac_addr, &secret, PROVISION_PENDING_MAX).await? {
+            Some(id) => Ok(id),
+            None => auth::fail(addr, mac_addr, "待确认设备过多").await,
+        };
+    }
+    match store::provision::upsert(mac_addr, &addr.to_string(), PROVISION_PENDING_MAX).await? {
+        Some(state::REJECTED) => auth::fail(addr, mac_addr, "设备已被拒绝接入").await,
+        Some(_) => auth::fail(addr, mac_addr, "设备等待审批").await,
+        None => auth::fail(addr, mac_addr, "待审批设备过多").await,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Approved {
+    pub device_id: i64,
+    // 十六进制密钥, 需写入设备后才能登录
+    pub secret: String,
+}
+
+pub async fn approve(
+    id: i64,
+    name: &str,
+    address: &str,
+    profile_id: Option<i64>,
+) -> Result<Approved, AppErr> {
+    let pending = store::provision::get(id).await?;
+    if let Some(profile_id) = profile_id {
+        store::profile::get(profile_id).await?;
+    }
+    let secret = auth::new_secret();
+    let device_id = match store::provision::approve(&pending, name, address, profile_id, &secret).await? {
+        Some(device_id) => device_id,
+        None => return conflict("MAC地址已存在"),
+    };
+    if profile_id.is_some() {
+        profile::apply(device_id, &[]).await.print_if_err();
+    }
+    Ok(Approved {
+        device_id,
+        secret: to_hex(&secret),
+    })
+}
+
+pub async fn reject(id: i64) -> Result<(), AppErr> {
+    store::provision::get(id).await?;
+    store::provision::set_state(id, state::REJECTED).await?;
+    Ok(())
+}
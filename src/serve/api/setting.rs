@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::cmd;
+use crate::{
+    error::AppErr,
+    serve::{conn::SharedConn, manager},
+    store::{self, setting::{SettingItem, TableSetting}},
+    utils::Array,
+};
+
+const SETTING_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct PushReq<'a> {
+    // true 时设备用 items 替换全部配置
+    full: bool,
+    version: i64,
+    items: &'a [SettingItem],
+}
+
+#[derive(Debug, Deserialize)]
+struct PushRes {
+    version: i64,
+    hash: String,
+}
+
+// 设备自身的配置覆盖所属配置方案中的同名项
+pub async fn expected(device_id: i64) -> Result<Array<TableSetting>, AppErr> {
+    let mut settings = match store::profile::get_device(device_id).await? {
+        Some(d) => store::profile::select_settings(d.profile_id).await?.into_vec(),
+        None => Vec::new(),
+    };
+    for item in store::setting::select(device_id).await?.into_vec() {
+        settings.retain(|v| v.key != item.key);
+        settings.push(item);
+    }
+    settings.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(settings.into_boxed_slice())
+}
+
+async fn push(conn: &SharedConn, full: bool) -> Result<bool, AppErr> {
+    let device_id = conn.info.id;
+    let state = store::setting::get_state(device_id).await?;
+    let settings = expected(device_id).await?;
+
+    let items: Vec<SettingItem> = if full {
+        settings
+            .iter()
+            .map(|v| SettingItem {
+                key: v.key.clone(),
+                value: Some(v.value.clone()),
+            })
+            .collect()
+    } else {
+        let keys = store::setting::select_changed_keys(device_id, state.applied_version).await?;
+        keys.iter()
+            .map(|key| SettingItem {
+                key: key.clone(),
+                value: settings.iter().find(|v| &v.key == key).map(|v| v.value.clone()),
+            })
+            .collect()
+    };
+
+    let req = PushReq {
+        full,
+        version: state.version,
+        items: &items,
+    };
+    let res: PushRes = conn.exec_req(cmd::SETTING_PUSH, &req, SETTING_TIMEOUT).await?;
+    store::setting::set_applied(device_id, res.version, &res.hash).await?;
+    Ok(res.hash == store::setting::hash(&settings))
+}
+
+// 设备上报的 hash 与期望不一致或增量下发后仍不一致时下发全部配置
+pub async fn sync(conn: &SharedConn) -> Result<(), AppErr> {
+    let device_id = conn.info.id;
+    let state = store::setting::get_state(device_id).await?;
+    let hash = store::setting::hash(&expected(device_id).await?);
+
+    let mut full = matches!(&state.reported_hash, Some(v) if *v != hash);
+    if state.applied_version < state.version {
+        full = !push(conn, false).await?;
+    }
+    if full {
+        push(conn, true).await?;
+    }
+    Ok(())
+}
+
+// 设备离线时不下发, 下次登录后由 sync 补发
+pub async fn push_changes(device_id: i64) -> Result<(), AppErr> {
+    if let Some(conn) = manager::find(device_id) {
+        sync(&conn).await?;
+    }
+    Ok(())
+}
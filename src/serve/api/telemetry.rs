@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::time;
+
+use super::clock;
+use crate::{
+    config::{TELEMETRY_HOUR_DAYS, TELEMETRY_RAW_DAYS},
+    error::{AppErr, ErrorExt},
+    serve::{conn::SharedConn, frame::recv::RequestFrame},
+    store::{self, telemetry::{metric, DAY}},
+    utils::current_timestamp,
+};
+
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Deserialize)]
+struct Report {
+    // 设备时间, 为空时使用服务器收到的时间
+    timestamp: Option<i64>,
+    temperature: Option<f64>,
+    voltage: Option<f64>,
+    signal: Option<f64>,
+    free_storage: Option<f64>,
+    uptime: Option<f64>,
+}
+
+pub(super) async fn on_report(conn: &SharedConn, frame: &RequestFrame) -> Result<(), AppErr> {
+    let report: Report = frame.parse()?;
+    let timestamp = match report.timestamp {
+        Some(ts) => clock::to_server_timestamp(conn.info.id, ts).await?,
+        None => current_timestamp(),
+    };
+    let values: Vec<(i32, f64)> = [
+        (metric::TEMPERATURE, report.temperature),
+        (metric::VOLTAGE, report.voltage),
+        (metric::SIGNAL, report.signal),
+        (metric::FREE_STORAGE, report.free_storage),
+        (metric::UPTIME, report.uptime),
+    ]
+    .into_iter()
+    .filter_map(|(m, v)| v.map(|v| (m, v)))
+    .collect();
+
+    store::telemetry::insert(conn.info.id, timestamp, &values).await?;
+    Ok(())
+}
+
+pub async fn run() {
+    let mut interval = time::interval(ROLLUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = current_timestamp();
+        store::telemetry::rollup_hour(now - TELEMETRY_RAW_DAYS * DAY)
+            .await
+            .print_if_err();
+        store::telemetry::rollup_day(now - TELEMETRY_HOUR_DAYS * DAY)
+            .await
+            .print_if_err();
+    }
+}
@@ -1,10 +1,10 @@
 use crate::{
-    error::{AppErr, ErrorExt, IoErr},
+    error::{code_err, err_code, AppErr, IoErr},
     utils::get_mut,
 };
 use dashmap::DashMap;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{sync::{Arc, atomic::{AtomicU8, Ordering}}, time::Duration};
+use std::{collections::HashSet, sync::{Arc, atomic::{AtomicU8, Ordering}}, time::{Duration, Instant}};
 use tokio::{
     net::TcpStream,
     sync::{mpsc, oneshot, Semaphore},
@@ -13,7 +13,7 @@ use tokio::{
 
 use super::{
     handler::handle_frame,
-    manager::conn_remove, frame::{send::{SendFrame, ResponseFrame, RequestFrame}, recv::{RecvFrame}, write, read, BaseFrame, frame_type, make_type_seq}, api::{ConnInfo},
+    manager::conn_remove, frame::{send::{SendFrame, ResponseFrame, RequestFrame}, recv::{RecvFrame}, write, read, BaseFrame, frame_type, make_type_seq}, api::{ConnInfo, ConnState},
 };
 
 
@@ -62,7 +62,7 @@ impl DeviceConn {
         let seq = self.get_seq();
         let rx = self.create_resp(make_type_seq(frame_type::SIMPLE_RES, seq));
         self.write(SendFrame::SimpleReq(RequestFrame::new(seq, cmd, value)))?;
-        let frame = time::timeout(Duration::from_secs(1), rx).await.wrap()?.wrap()?;
+        let frame = wait_resp(rx, Duration::from_secs(1)).await?;
         let frame = frame.simple_res()?;
         let v = frame.parse()?;
         Ok(v)
@@ -77,12 +77,14 @@ impl DeviceConn {
         let seq = self.get_seq();
         let ack_rx = self.create_resp(make_type_seq(frame_type::ACK, seq));
         let res_rx = self.create_resp(make_type_seq(frame_type::RES, seq));
+        let start = Instant::now();
         self.write( SendFrame::Req(RequestFrame::new(seq, cmd, value)) )?;
 
-        let ack = time::timeout(Duration::from_secs(1), ack_rx).await.wrap()?.wrap()?;
+        let ack = wait_resp(ack_rx, Duration::from_secs(1)).await?;
         ack.ack()?;
+        self.info.set_rtt(start.elapsed());
 
-        let frame = time::timeout(timeout, res_rx).await.wrap()?.wrap()?;
+        let frame = wait_resp(res_rx, timeout).await?;
         let frame = frame.res()?;
         let r = frame.parse()?;
         Ok(r)
@@ -91,15 +93,21 @@ impl DeviceConn {
     pub async fn exec_ping(&self) -> Result<(), AppErr> {
         let seq = self.get_seq();
         let rx = self.create_resp(make_type_seq(frame_type::PONG, seq));
+        let start = Instant::now();
         self.write(SendFrame::Ping(BaseFrame{ seq }))?;
-        let frame = time::timeout(Duration::from_secs(1), rx).await.wrap()?.wrap()?;
+        let frame = wait_resp(rx, Duration::from_secs(1)).await?;
         frame.pong()?;
+        self.info.set_rtt(start.elapsed());
         Ok(())
     }
 
     pub fn write(&self, frame: SendFrame) -> Result<(), AppErr> {
-        self.write_tx.try_send(frame).wrap()?;
-        Ok(())
+        match self.write_tx.try_send(frame) {
+            Ok(_) => Ok(()),
+            // 写队列已满, 稍后重试
+            Err(mpsc::error::TrySendError::Full(_)) => code_err(err_code::BUSY, "设备写队列已满"),
+            Err(mpsc::error::TrySendError::Closed(_)) => code_err(err_code::DEVICE_OFFLINE, "设备连接已关闭"),
+        }
     }
 
     pub fn ack(&self, seq: u8) -> Result<(), AppErr> {
@@ -117,6 +125,22 @@ impl DeviceConn {
         self.write(SendFrame::SimpleRes(frame))
     }
 
+    pub fn state(&self) -> ConnState {
+        // 每个调用在 ack 和响应到达前各占一项, 按 seq 去重
+        let seqs: HashSet<u16> = self.res_mq.iter().map(|v| *v.key() & 0xFF).collect();
+        ConnState {
+            session_id: self.info.session_id,
+            device_id: self.info.id,
+            mac_addr: self.info.mac_addr.clone(),
+            addr: self.info.addr.to_string(),
+            connected_since: self.info.connected_since,
+            ping_count: self.info.ping_count.load(Ordering::SeqCst),
+            rtt_ms: self.info.rtt_ms.load(Ordering::SeqCst),
+            queue_depth: self.write_tx.max_capacity() - self.write_tx.capacity(),
+            pending_calls: seqs.len(),
+        }
+    }
+
     fn get_seq(&self) -> u8 {
         self.seq.fetch_add(1, Ordering::SeqCst)
     }
@@ -146,6 +170,15 @@ impl DeviceConn {
     }
 }
 
+// 发送端被丢弃说明连接已关闭
+async fn wait_resp(rx: oneshot::Receiver<RecvFrame>, timeout: Duration) -> Result<RecvFrame, AppErr> {
+    match time::timeout(timeout, rx).await {
+        Ok(Ok(frame)) => Ok(frame),
+        Ok(Err(_)) => code_err(err_code::DEVICE_OFFLINE, "设备连接已断开"),
+        Err(_) => code_err(err_code::DEVICE_TIMEOUT, "设备响应超时"),
+    }
+}
+
 async fn read_loop(conn: SharedConn) {
     loop {
         let ret = tokio::select! {
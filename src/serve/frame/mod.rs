@@ -129,8 +129,6 @@ pub trait ToFrameBody {
     fn to_body(&self) -> Body;
 
     fn to_res(&self) -> Result<Body, AppErr>;
-
-    fn to_vec(&self) -> Vec<u8>;
 }
 
 impl <T: Serialize> ToFrameBody for T {
@@ -139,10 +137,6 @@ impl <T: Serialize> ToFrameBody for T {
         serde_cbor::to_vec(self).unwrap().into_boxed_slice()
     }
 
-    fn to_vec(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).unwrap()
-    }
-
     fn to_res(&self) -> Result<Body, AppErr> {
         Ok(self.to_body())
     }
@@ -1,6 +1,6 @@
 use super::{
     conn::SharedConn,
-    frame::{recv::RecvFrame, send::SendFrame, BaseFrame}, api::handle_req,
+    frame::{recv::RecvFrame, send::SendFrame, BaseFrame}, api::{handle_notify, handle_req},
 };
 
 
@@ -17,8 +17,13 @@ pub async fn handle_frame(conn: &SharedConn, frame: RecvFrame) {
             tokio::spawn(handle_req(conn.clone(), r));
         },
 
+        RecvFrame::Notify(r) => {
+            tokio::spawn(handle_notify(conn.clone(), r));
+        },
+
         RecvFrame::NotifyAck(r) => {
             _ = conn.write(SendFrame::Ack(BaseFrame { seq: r.seq }));
+            tokio::spawn(handle_notify(conn.clone(), r));
         },
 
         _ => {}
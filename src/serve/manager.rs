@@ -1,6 +1,4 @@
-use crate::serve::frame::ToFrameBody;
-
-use super::{conn::SharedConn, api::ConnInfo};
+use super::{api::{event::{self, kind}, ConnState}, conn::SharedConn};
 use dashmap::DashSet;
 use std::mem::MaybeUninit;
 
@@ -25,22 +23,56 @@ fn get_manager() -> &'static Manager {
 
 pub fn conn_append(conn: SharedConn) {
     let m = get_manager();
+    event::publish(Some(conn.info.id), kind::ONLINE, &conn.state());
     m.hub.insert(conn);
 }
 
 pub fn conn_remove(conn: &SharedConn) {
     let m = get_manager();
-    m.hub.remove(conn);
+    if m.hub.remove(conn).is_some() {
+        event::publish(Some(conn.info.id), kind::OFFLINE, &conn.state());
+    }
 }
 
-pub fn conn_infos() -> Vec<u8> {
-    let manager = unsafe {
-        MANAGER.assume_init_ref()  
-    };
-    let mut vec: Vec<SharedConn> = Vec::with_capacity(manager.hub.len());
-    for conn in manager.hub.iter() {
-        vec.push(conn.clone());
+pub fn find(device_id: i64) -> Option<SharedConn> {
+    let m = get_manager();
+    m.hub
+        .iter()
+        .find(|conn| conn.info.id == device_id)
+        .map(|conn| conn.key().clone())
+}
+
+pub fn device_ids() -> Vec<i64> {
+    let m = get_manager();
+    m.hub.iter().map(|conn| conn.info.id).collect()
+}
+
+// device_id 精确匹配, addr 为远端地址子串匹配
+pub fn conn_states(device_id: Option<i64>, addr: Option<&str>) -> Vec<ConnState> {
+    let m = get_manager();
+    let mut vec: Vec<ConnState> = m
+        .hub
+        .iter()
+        .filter(|conn| device_id.is_none_or(|id| conn.info.id == id))
+        .filter(|conn| addr.is_none_or(|addr| conn.info.addr.to_string().contains(addr)))
+        .map(|conn| conn.state())
+        .collect();
+    vec.sort_by_key(|v| v.session_id);
+    vec
+}
+
+pub fn kick(session_id: u64) -> bool {
+    let m = get_manager();
+    let conn = m
+        .hub
+        .iter()
+        .find(|conn| conn.info.session_id == session_id)
+        .map(|conn| conn.key().clone());
+    match conn {
+        Some(conn) => {
+            conn.exit();
+            true
+        }
+        None => false,
     }
-    let is: Vec<&ConnInfo> = vec.iter().map(|c| &c.info).collect();
-    is.to_vec()
 }
@@ -1,10 +1,10 @@
 use std::net::SocketAddr;
 
-use self::{conn::DeviceConn, manager::conn_append, api::wait_login};
+use self::{conn::DeviceConn, manager::conn_append, api::{wait_login, after_login}};
 use crate::{config::DEVICE_ADDR, error::AppErr};
 use tokio::net::{TcpListener, TcpStream};
 
-mod api;
+pub mod api;
 mod conn;
 mod handler;
 mod manager;
@@ -20,6 +20,9 @@ pub async fn run() {
 
     let serve = TcpListener::bind(DEVICE_ADDR).await.unwrap();
     tokio::spawn(inner_run(serve));
+    tokio::spawn(api::campaign::run());
+    tokio::spawn(api::telemetry::run());
+    tokio::spawn(api::inhibit::run());
 }
 
 async fn inner_run(serve: TcpListener) {
@@ -46,7 +49,8 @@ async fn try_wait_login(stream: TcpStream, addr: SocketAddr) {
 async fn do_login(mut stream: TcpStream, addr: SocketAddr) -> Result<(), AppErr> {
     let info = wait_login(&mut stream, addr).await?;
     let conn = DeviceConn::new(stream, info);
-    conn_append(conn);
+    conn_append(conn.clone());
+    tokio::spawn(after_login(conn));
     Ok(())
 }
 
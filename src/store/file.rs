@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{config::{FIRMWARE_PATH, LOG_PATH}, error::IoErr};
+
+pub fn log_path(device_id: i64, id: i64) -> PathBuf {
+    PathBuf::from(LOG_PATH)
+        .join(device_id.to_string())
+        .join(format!("{}.log", id))
+}
+
+pub fn firmware_path(hash: &str) -> PathBuf {
+    PathBuf::from(FIRMWARE_PATH).join(format!("{}.bin", hash))
+}
+
+pub async fn write(path: &PathBuf, data: &[u8]) -> Result<(), IoErr> {
+    fs::write(path, data).await
+}
+
+pub async fn create(path: &PathBuf) -> Result<(), IoErr> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    fs::File::create(path).await?;
+    Ok(())
+}
+
+pub async fn append(path: &PathBuf, data: &[u8]) -> Result<(), IoErr> {
+    let mut file = fs::OpenOptions::new().append(true).open(path).await?;
+    file.write_all(data).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+pub async fn read(path: &PathBuf) -> Result<Vec<u8>, IoErr> {
+    fs::read(path).await
+}
+
+pub async fn remove(path: &PathBuf) -> Result<(), IoErr> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
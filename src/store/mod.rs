@@ -1,3 +1,5 @@
 mod sql;
 
+pub mod file;
+
 pub use sql::*;
@@ -0,0 +1,155 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+// ip_allowlist 为逗号分隔的IP, 为空表示不限制
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_api_key (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        name TEXT NOT NULL, 
+        key_hash TEXT NOT NULL, 
+        key_prefix TEXT NOT NULL, 
+        scopes INTEGER NOT NULL, 
+        expire_timestamp INTEGER, 
+        ip_allowlist TEXT, 
+        user_id INTEGER NOT NULL, 
+        revoked INTEGER NOT NULL DEFAULT 0, 
+        create_timestamp INTEGER NOT NULL, 
+        last_used_timestamp INTEGER, 
+        UNIQUE(key_hash)
+    )
+"#;
+
+// 最后使用时间的最小更新间隔, 秒
+const TOUCH_INTERVAL: i64 = 60;
+
+#[derive(Debug, Serialize)]
+pub struct TableApiKey {
+    pub id: i64,
+    pub name: String,
+    // 密钥前几位, 便于识别
+    pub key_prefix: String,
+    pub scopes: u32,
+    pub expire_timestamp: Option<i64>,
+    pub ip_allowlist: Option<String>,
+    pub user_id: i64,
+    pub revoked: bool,
+    pub create_timestamp: i64,
+    pub last_used_timestamp: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct ApiKeyOwner {
+    pub key: TableApiKey,
+    pub username: String,
+    pub role: i32,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT 
+    k.id, k.name, k.key_prefix, k.scopes, k.expire_timestamp, k.ip_allowlist, k.user_id, 
+    k.revoked, k.create_timestamp, k.last_used_timestamp, u.username, u.role 
+    FROM tb_api_key k JOIN tb_user u ON u.id = k.user_id
+"#;
+
+fn to_api_key(row: &SqliteRow) -> TableApiKey {
+    TableApiKey {
+        id: row.get(0),
+        name: row.get(1),
+        key_prefix: row.get(2),
+        scopes: row.get(3),
+        expire_timestamp: row.get(4),
+        ip_allowlist: row.get(5),
+        user_id: row.get(6),
+        revoked: row.get(7),
+        create_timestamp: row.get(8),
+        last_used_timestamp: row.get(9),
+    }
+}
+
+pub async fn create(
+    name: &str,
+    key_hash: &str,
+    key_prefix: &str,
+    scopes: u32,
+    expire_timestamp: Option<i64>,
+    ip_allowlist: Option<&str>,
+    user_id: i64,
+) -> Result<i64, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_api_key 
+        (name, key_hash, key_prefix, scopes, expire_timestamp, ip_allowlist, user_id, create_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(name)
+    .bind(key_hash)
+    .bind(key_prefix)
+    .bind(scopes)
+    .bind(expire_timestamp)
+    .bind(ip_allowlist)
+    .bind(user_id)
+    .bind(current_timestamp())
+    .execute(get_pool())
+    .await?;
+    Ok(ret.last_insert_rowid())
+}
+
+// 包含已吊销和已过期的密钥, 由调用方判断
+pub async fn get_by_hash(key_hash: &str) -> Result<Option<ApiKeyOwner>, SqlxErr> {
+    let sql = format!("{} WHERE k.key_hash = ?", SELECT_SQL);
+    let row = sqlx::query(&sql)
+        .bind(key_hash)
+        .fetch_optional(get_pool())
+        .await?;
+    Ok(row.map(|row| ApiKeyOwner {
+        key: to_api_key(&row),
+        username: row.get(10),
+        role: row.get(11),
+    }))
+}
+
+pub async fn get(id: i64) -> Result<TableApiKey, SqlxErr> {
+    let sql = format!("{} WHERE k.id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_api_key(&row))
+}
+
+pub async fn select() -> Result<Array<TableApiKey>, SqlxErr> {
+    let sql = format!("{} ORDER BY k.id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql).fetch_all(get_pool()).await?;
+    let vec: Vec<TableApiKey> = rows.iter().map(to_api_key).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn revoke(id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_api_key SET revoked = 1 WHERE id = ?")
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn touch(id: i64) -> Result<(), SqlxErr> {
+    let now = current_timestamp();
+    sqlx::query(
+        r#"
+        UPDATE tb_api_key SET last_used_timestamp = ? 
+        WHERE id = ? AND (last_used_timestamp IS NULL OR last_used_timestamp <= ?)
+    "#,
+    )
+    .bind(now)
+    .bind(id)
+    .bind(now - TOUCH_INTERVAL)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+}
@@ -0,0 +1,157 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, QueryBuilder, Row, Sqlite};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+// before_value 和 after_value 为 JSON 文本, 远程命令失败时 err_msg 为错误信息
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_audit (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        user_id INTEGER NOT NULL, 
+        actor TEXT NOT NULL, 
+        api_key_id INTEGER, 
+        action TEXT NOT NULL, 
+        device_id INTEGER, 
+        before_value TEXT, 
+        after_value TEXT, 
+        err_msg TEXT, 
+        source_ip TEXT NOT NULL, 
+        create_timestamp INTEGER NOT NULL
+    )
+"#;
+
+const CREATE_INDEX_SQL: &str = r#"
+    CREATE INDEX IF NOT EXISTS idx_audit_device ON tb_audit (device_id, create_timestamp)
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct TableAudit {
+    pub id: i64,
+    pub user_id: i64,
+    pub actor: String,
+    pub api_key_id: Option<i64>,
+    pub action: String,
+    pub device_id: Option<i64>,
+    pub before_value: Option<String>,
+    pub after_value: Option<String>,
+    pub err_msg: Option<String>,
+    pub source_ip: String,
+    pub create_timestamp: i64,
+}
+
+pub struct NewAudit<'a> {
+    pub user_id: i64,
+    pub actor: &'a str,
+    pub api_key_id: Option<i64>,
+    pub action: &'a str,
+    pub device_id: Option<i64>,
+    pub before_value: Option<&'a str>,
+    pub after_value: Option<&'a str>,
+    pub err_msg: Option<&'a str>,
+    pub source_ip: &'a str,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter<'a> {
+    pub actor: Option<&'a str>,
+    // 前缀匹配, 如 device. 匹配所有设备操作
+    pub action: Option<&'a str>,
+    pub device_id: Option<i64>,
+    pub start_timestamp: Option<i64>,
+    pub end_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub total: i64,
+    pub items: Array<TableAudit>,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT id, user_id, actor, api_key_id, action, device_id, before_value, after_value, 
+    err_msg, source_ip, create_timestamp FROM tb_audit
+"#;
+
+fn to_audit(row: &SqliteRow) -> TableAudit {
+    TableAudit {
+        id: row.get(0),
+        user_id: row.get(1),
+        actor: row.get(2),
+        api_key_id: row.get(3),
+        action: row.get(4),
+        device_id: row.get(5),
+        before_value: row.get(6),
+        after_value: row.get(7),
+        err_msg: row.get(8),
+        source_ip: row.get(9),
+        create_timestamp: row.get(10),
+    }
+}
+
+pub async fn create(audit: &NewAudit<'_>) -> Result<i64, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_audit 
+        (user_id, actor, api_key_id, action, device_id, before_value, after_value, err_msg, source_ip, create_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(audit.user_id)
+    .bind(audit.actor)
+    .bind(audit.api_key_id)
+    .bind(audit.action)
+    .bind(audit.device_id)
+    .bind(audit.before_value)
+    .bind(audit.after_value)
+    .bind(audit.err_msg)
+    .bind(audit.source_ip)
+    .bind(current_timestamp())
+    .execute(get_pool())
+    .await?;
+    Ok(ret.last_insert_rowid())
+}
+
+fn push_filter<'a>(qb: &mut QueryBuilder<'a, Sqlite>, filter: &AuditFilter<'a>) {
+    qb.push(" WHERE 1 = 1");
+    if let Some(actor) = filter.actor {
+        qb.push(" AND actor = ").push_bind(actor);
+    }
+    if let Some(action) = filter.action {
+        qb.push(" AND substr(action, 1, length(").push_bind(action);
+        qb.push(")) = ").push_bind(action);
+    }
+    if let Some(device_id) = filter.device_id {
+        qb.push(" AND device_id = ").push_bind(device_id);
+    }
+    if let Some(start) = filter.start_timestamp {
+        qb.push(" AND create_timestamp >= ").push_bind(start);
+    }
+    if let Some(end) = filter.end_timestamp {
+        qb.push(" AND create_timestamp < ").push_bind(end);
+    }
+}
+
+pub async fn select(filter: &AuditFilter<'_>, offset: i64, limit: i64) -> Result<AuditPage, SqlxErr> {
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM tb_audit");
+    push_filter(&mut qb, filter);
+    let total: i64 = qb.build().fetch_one(get_pool()).await?.get(0);
+
+    let mut qb = QueryBuilder::new(SELECT_SQL);
+    push_filter(&mut qb, filter);
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(limit);
+    qb.push(" OFFSET ").push_bind(offset);
+    let rows = qb.build().fetch_all(get_pool()).await?;
+    let items: Vec<TableAudit> = rows.iter().map(to_audit).collect();
+
+    Ok(AuditPage {
+        total,
+        items: items.into_boxed_slice(),
+    })
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+    get_pool().execute(CREATE_INDEX_SQL).await.unwrap();
+}
@@ -0,0 +1,192 @@
+use serde::Serialize;
+use sqlx::{Executor, Row, SqliteConnection};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{add_column, get_pool};
+
+const CREATE_SECRET_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_device_secret (
+        device_id INTEGER PRIMARY KEY, 
+        secret BLOB NOT NULL, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL
+    )
+"#;
+
+// 自动登记的设备在确认收到密钥前可以免签名登录
+const PROVISIONAL_COLUMN: &str = "INTEGER NOT NULL DEFAULT 0";
+
+const CREATE_FAIL_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_auth_fail (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        mac_addr TEXT NOT NULL, 
+        addr TEXT NOT NULL, 
+        reason TEXT NOT NULL, 
+        create_timestamp INTEGER NOT NULL
+    )
+"#;
+
+const CREATE_FAIL_INDEX_SQL: &str = r#"
+    CREATE INDEX IF NOT EXISTS idx_auth_fail_mac ON tb_auth_fail (mac_addr, create_timestamp)
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct TableAuthFail {
+    pub id: i64,
+    pub mac_addr: String,
+    pub addr: String,
+    pub reason: String,
+    pub create_timestamp: i64,
+}
+
+pub struct Secrets {
+    pub secret: Vec<u8>,
+    // 轮换中的新密钥, 设备首次用它登录后替换 secret
+    pub pending: Option<Vec<u8>>,
+    pub provisional: bool,
+}
+
+pub async fn get_secrets(device_id: i64) -> Result<Option<Secrets>, SqlxErr> {
+    let row = sqlx::query(
+        "SELECT secret, pending_secret, provisional FROM tb_device_secret WHERE device_id = ?",
+    )
+    .bind(device_id)
+    .fetch_optional(get_pool())
+    .await?;
+    Ok(row.map(|row| Secrets {
+        secret: row.get(0),
+        pending: row.get(1),
+        provisional: row.get(2),
+    }))
+}
+
+pub async fn set_secret(device_id: i64, secret: &[u8]) -> Result<(), SqlxErr> {
+    let mut conn = get_pool().acquire().await?;
+    set_secret_by(&mut conn, device_id, secret, false).await
+}
+
+pub(super) async fn set_secret_by(
+    conn: &mut SqliteConnection,
+    device_id: i64,
+    secret: &[u8],
+    provisional: bool,
+) -> Result<(), SqlxErr> {
+    let now = current_timestamp();
+    sqlx::query(
+        r#"
+        INSERT INTO tb_device_secret (device_id, secret, provisional, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, ?, ?) 
+        ON CONFLICT(device_id) DO UPDATE SET 
+        secret = excluded.secret, pending_secret = NULL, provisional = excluded.provisional, 
+        update_timestamp = excluded.update_timestamp
+    "#,
+    )
+    .bind(device_id)
+    .bind(secret)
+    .bind(provisional)
+    .bind(now)
+    .bind(now)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// 设备确认保存自动登记时分配的密钥
+pub async fn confirm(device_id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_device_secret SET provisional = 0, update_timestamp = ? WHERE device_id = ?")
+        .bind(current_timestamp())
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn count_provisional(conn: &mut SqliteConnection) -> Result<i64, SqlxErr> {
+    let row = sqlx::query("SELECT COUNT(*) FROM tb_device_secret WHERE provisional = 1")
+        .fetch_one(conn)
+        .await?;
+    Ok(row.get(0))
+}
+
+// 返回是否已有密钥
+pub async fn set_pending(device_id: i64, secret: &[u8]) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query(
+        "UPDATE tb_device_secret SET pending_secret = ?, update_timestamp = ? WHERE device_id = ?",
+    )
+    .bind(secret)
+    .bind(current_timestamp())
+    .bind(device_id)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+// 只在 pending_secret 仍为登录时校验的值时替换, 避免覆盖期间发起的新一轮轮换
+pub async fn promote(device_id: i64, secret: &[u8]) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_device_secret SET secret = pending_secret, pending_secret = NULL, update_timestamp = ? 
+        WHERE device_id = ? AND pending_secret = ?
+    "#,
+    )
+    .bind(current_timestamp())
+    .bind(device_id)
+    .bind(secret)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn add_fail(mac_addr: &str, addr: &str, reason: &str) -> Result<(), SqlxErr> {
+    sqlx::query(
+        "INSERT INTO tb_auth_fail (mac_addr, addr, reason, create_timestamp) VALUES (?, ?, ?, ?)",
+    )
+    .bind(mac_addr)
+    .bind(addr)
+    .bind(reason)
+    .bind(current_timestamp())
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn select_fail(
+    mac_addr: Option<&str>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+) -> Result<Array<TableAuthFail>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, mac_addr, addr, reason, create_timestamp FROM tb_auth_fail 
+        WHERE (? IS NULL OR mac_addr = ?) AND create_timestamp >= ? AND create_timestamp < ? 
+        ORDER BY id DESC
+    "#,
+    )
+    .bind(mac_addr)
+    .bind(mac_addr)
+    .bind(start_timestamp)
+    .bind(end_timestamp)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<TableAuthFail> = rows
+        .iter()
+        .map(|row| TableAuthFail {
+            id: row.get(0),
+            mac_addr: row.get(1),
+            addr: row.get(2),
+            reason: row.get(3),
+            create_timestamp: row.get(4),
+        })
+        .collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SECRET_SQL).await.unwrap();
+    add_column("tb_device_secret", "pending_secret", "BLOB").await;
+    add_column("tb_device_secret", "provisional", PROVISIONAL_COLUMN).await;
+    get_pool().execute(CREATE_FAIL_SQL).await.unwrap();
+    get_pool().execute(CREATE_FAIL_INDEX_SQL).await.unwrap();
+}
@@ -3,13 +3,14 @@ use sqlx::{Executor, Row, SqliteConnection};
 
 use crate::error::SqlxErr;
 
-use super::get_pool;
+use super::{add_column, get_pool};
 
 const COIN_CREATE_SQL: &'static str = r#"
     CREATE TABLE IF NOT EXISTS tb_bill (
         id INTEGER PRIMARY KEY AUTOINCREMENT, 
         device_id INTEGER NOT NULL, 
         type_mask INTEGER NOT NULL, 
+        applied_mask INTEGER NOT NULL DEFAULT 0, 
         serial_number TEXT NOT NULL, 
         model TEXT NOT NULL,
         version TEXT NOT NULL,
@@ -22,6 +23,7 @@ pub struct TableBill {
     pub id: i64,
     pub device_id: i64,
     pub type_mask: u32,
+    pub applied_mask: u32,
     pub serial_number: String,
     pub model: String,
     pub version: String,
@@ -47,6 +49,19 @@ pub async fn create(conn: &mut SqliteConnection, device_id: i64) -> Result<(), S
     Ok(())
 }
 
+pub async fn set_applied_mask(device_id: i64, applied_mask: u32) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_bill SET applied_mask = ? WHERE device_id = ?
+    "#,
+    )
+    .bind(applied_mask)
+    .bind(device_id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
 pub async fn update(device_id: i64, model: &str, version: &str, serial_number: &str) -> Result<(), SqlxErr> {
     sqlx::query(
         r#"
@@ -78,7 +93,7 @@ pub async fn set_type_mask(device_id: i64, type_mask: u32) -> Result<(), SqlxErr
 pub async fn get(device_id: i64) -> Result<TableBill, SqlxErr> {
     let row = sqlx::query(
         r#"
-        SELECT id, device_id, type_mask, serial_number, model, version 
+        SELECT id, device_id, type_mask, applied_mask, serial_number, model, version 
         FROM tb_bill WHERE device_id = ?
     "#,
     )
@@ -90,9 +105,10 @@ pub async fn get(device_id: i64) -> Result<TableBill, SqlxErr> {
         id: row.get(0),
         device_id: row.get(1),
         type_mask: row.get(2),
-        serial_number: row.get(3),
-        model: row.get(4),
-        version: row.get(5),
+        applied_mask: row.get(3),
+        serial_number: row.get(4),
+        model: row.get(5),
+        version: row.get(6),
     };
 
     Ok(coin)
@@ -103,6 +119,7 @@ pub async fn get(device_id: i64) -> Result<TableBill, SqlxErr> {
 
 pub async fn init() {
     get_pool().execute(COIN_CREATE_SQL).await.unwrap();
+    add_column("tb_bill", "applied_mask", "INTEGER NOT NULL DEFAULT 0").await;
 }
 
 
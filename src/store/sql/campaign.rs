@@ -0,0 +1,302 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{firmware, get_pool};
+
+const CAMPAIGN_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_campaign (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        name TEXT NOT NULL, 
+        firmware_id INTEGER NOT NULL, 
+        waves TEXT NOT NULL, 
+        failure_threshold INTEGER NOT NULL, 
+        state INTEGER NOT NULL, 
+        current_wave INTEGER NOT NULL, 
+        err_msg TEXT, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL
+    )
+"#;
+
+const CAMPAIGN_DEVICE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_campaign_device (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        campaign_id INTEGER NOT NULL, 
+        device_id INTEGER NOT NULL, 
+        wave INTEGER NOT NULL, 
+        update_id INTEGER, 
+        UNIQUE(campaign_id, device_id)
+    )
+"#;
+
+pub mod state {
+    pub const RUNNING: i32 = 0;
+    pub const PAUSED: i32 = 1;
+    pub const FINISHED: i32 = 2;
+    pub const CANCELLED: i32 = 3;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableCampaign {
+    pub id: i64,
+    pub name: String,
+    pub firmware_id: i64,
+    // 每一波累计发布的设备百分比
+    pub waves: Array<u8>,
+    pub failure_threshold: i64,
+    pub state: i32,
+    pub current_wave: i64,
+    pub err_msg: Option<String>,
+    pub create_timestamp: i64,
+    pub update_timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaveStats {
+    pub wave: i64,
+    pub total: i64,
+    pub unreleased: i64,
+    pub pending: i64,
+    pub downloading: i64,
+    pub installing: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT 
+    id, name, firmware_id, waves, failure_threshold, state, current_wave, err_msg, 
+    create_timestamp, update_timestamp 
+    FROM tb_campaign
+"#;
+
+fn to_campaign(row: &SqliteRow) -> TableCampaign {
+    let waves: String = row.get(3);
+    TableCampaign {
+        id: row.get(0),
+        name: row.get(1),
+        firmware_id: row.get(2),
+        waves: waves.split(',').filter_map(|v| v.parse().ok()).collect(),
+        failure_threshold: row.get(4),
+        state: row.get(5),
+        current_wave: row.get(6),
+        err_msg: row.get(7),
+        create_timestamp: row.get(8),
+        update_timestamp: row.get(9),
+    }
+}
+
+// devices: (device_id, wave)
+pub async fn create(
+    name: &str,
+    firmware_id: i64,
+    waves: &[u8],
+    failure_threshold: u8,
+    devices: &[(i64, usize)],
+) -> Result<i64, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    let now = current_timestamp();
+    let waves: Vec<String> = waves.iter().map(|v| v.to_string()).collect();
+
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_campaign 
+        (name, firmware_id, waves, failure_threshold, state, current_wave, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(name)
+    .bind(firmware_id)
+    .bind(waves.join(","))
+    .bind(failure_threshold)
+    .bind(state::RUNNING)
+    .bind(0)
+    .bind(now)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+    let id = ret.last_insert_rowid();
+
+    for (device_id, wave) in devices {
+        sqlx::query("INSERT INTO tb_campaign_device (campaign_id, device_id, wave) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(device_id)
+            .bind(*wave as i64)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn get(id: i64) -> Result<TableCampaign, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_campaign(&row))
+}
+
+pub async fn select() -> Result<Array<TableCampaign>, SqlxErr> {
+    let sql = format!("{} ORDER BY id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql).fetch_all(get_pool()).await?;
+    let vec: Vec<TableCampaign> = rows.iter().map(to_campaign).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn select_by_state(state: i32) -> Result<Array<TableCampaign>, SqlxErr> {
+    let sql = format!("{} WHERE state = ?", SELECT_SQL);
+    let rows = sqlx::query(&sql).bind(state).fetch_all(get_pool()).await?;
+    let vec: Vec<TableCampaign> = rows.iter().map(to_campaign).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 只在当前状态仍为 from 时修改, 状态已被其他操作改变时返回 false
+pub async fn set_state(id: i64, from: i32, state: i32, err_msg: Option<&str>) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query(
+        "UPDATE tb_campaign SET state = ?, err_msg = ?, update_timestamp = ? WHERE id = ? AND state = ?",
+    )
+    .bind(state)
+    .bind(err_msg)
+    .bind(current_timestamp())
+    .bind(id)
+    .bind(from)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+// 从暂停恢复运行, failure_threshold 不为空时一并修改
+pub async fn resume(id: i64, failure_threshold: Option<u8>) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        UPDATE tb_campaign SET state = ?, err_msg = NULL, 
+        failure_threshold = COALESCE(?, failure_threshold), update_timestamp = ? 
+        WHERE id = ? AND state = ?
+    "#,
+    )
+    .bind(state::RUNNING)
+    .bind(failure_threshold)
+    .bind(current_timestamp())
+    .bind(id)
+    .bind(state::PAUSED)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+// 同时取消已下发但未开始安装的升级, 已结束的返回 false
+pub async fn cancel(id: i64) -> Result<bool, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    let now = current_timestamp();
+    let ret = sqlx::query(
+        "UPDATE tb_campaign SET state = ?, err_msg = NULL, update_timestamp = ? WHERE id = ? AND state IN (?, ?)",
+    )
+    .bind(state::CANCELLED)
+    .bind(now)
+    .bind(id)
+    .bind(state::RUNNING)
+    .bind(state::PAUSED)
+    .execute(&mut *tx)
+    .await?;
+    if ret.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query(
+        r#"
+        UPDATE tb_firmware_update SET state = ?, err_msg = ?, update_timestamp = ? 
+        WHERE state < ? AND id IN (
+            SELECT update_id FROM tb_campaign_device WHERE campaign_id = ? AND update_id IS NOT NULL
+        )
+    "#,
+    )
+    .bind(firmware::state::CANCELLED)
+    .bind("campaign cancelled")
+    .bind(now)
+    .bind(firmware::state::INSTALLING)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 暂停或取消后不再推进
+pub async fn set_current_wave(id: i64, wave: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_campaign SET current_wave = ?, update_timestamp = ? WHERE id = ? AND state = ?")
+        .bind(wave)
+        .bind(current_timestamp())
+        .bind(id)
+        .bind(state::RUNNING)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn select_unreleased(campaign_id: i64, wave: i64) -> Result<Array<i64>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT device_id FROM tb_campaign_device 
+        WHERE campaign_id = ? AND wave = ? AND update_id IS NULL
+    "#,
+    )
+    .bind(campaign_id)
+    .bind(wave)
+    .fetch_all(get_pool())
+    .await?;
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn set_released(campaign_id: i64, device_id: i64, update_id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_campaign_device SET update_id = ? WHERE campaign_id = ? AND device_id = ?")
+        .bind(update_id)
+        .bind(campaign_id)
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn wave_stats(campaign_id: i64) -> Result<Array<WaveStats>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT d.wave, COUNT(*), 
+        SUM(CASE WHEN d.update_id IS NULL THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 0 THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 1 THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 2 THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 3 THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 4 THEN 1 ELSE 0 END), 
+        SUM(CASE WHEN u.state = 5 THEN 1 ELSE 0 END) 
+        FROM tb_campaign_device d LEFT JOIN tb_firmware_update u ON d.update_id = u.id 
+        WHERE d.campaign_id = ? GROUP BY d.wave ORDER BY d.wave
+    "#,
+    )
+    .bind(campaign_id)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<WaveStats> = rows
+        .iter()
+        .map(|row| WaveStats {
+            wave: row.get(0),
+            total: row.get(1),
+            unreleased: row.get(2),
+            pending: row.get(3),
+            downloading: row.get(4),
+            installing: row.get(5),
+            succeeded: row.get(6),
+            failed: row.get(7),
+            cancelled: row.get(8),
+        })
+        .collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn init() {
+    get_pool().execute(CAMPAIGN_CREATE_SQL).await.unwrap();
+    get_pool().execute(CAMPAIGN_DEVICE_CREATE_SQL).await.unwrap();
+}
@@ -1,15 +1,16 @@
 use serde::{Deserialize, Serialize};
 use sqlx::{Executor, Row, SqliteConnection};
 
-use crate::{error::SqlxErr, utils::Array};
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
 
-use super::get_pool;
+use super::{add_column, get_pool};
 
 const COIN_CREATE_SQL: &'static str = r#"
     CREATE TABLE IF NOT EXISTS tb_coin (
         id INTEGER PRIMARY KEY AUTOINCREMENT, 
         device_id INTEGER NOT NULL, 
         type_mask INTEGER NOT NULL, 
+        applied_mask INTEGER NOT NULL DEFAULT 0, 
         serial_number TEXT NOT NULL, 
         model TEXT NOT NULL,
         version TEXT NOT NULL,
@@ -28,11 +29,27 @@ const COIN_INFO_CREATE_SQL: &'static str = r#"
     )
 "#;
 
+const COIN_INFO_HISTORY_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_coin_info_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        reason INTEGER NOT NULL, 
+        infos BLOB NOT NULL, 
+        create_timestamp INTEGER NOT NULL
+    )
+"#;
+
+const COIN_INFO_HISTORY_INDEX_SQL: &str = r#"
+    CREATE INDEX IF NOT EXISTS idx_coin_info_history 
+    ON tb_coin_info_history (device_id, create_timestamp)
+"#;
+
 #[derive(Debug, Serialize)]
 pub struct TableCoin {
     pub id: i64,
     pub device_id: i64,
     pub type_mask: u32,
+    pub applied_mask: u32,
     pub serial_number: String,
     pub model: String,
     pub version: String,
@@ -51,6 +68,14 @@ pub struct TableCoinInfos {
     pub infos: Array<TableCoinInfo>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct TableCoinInfoHistory {
+    pub id: i64,
+    pub reason: u8,
+    pub infos: Array<TableCoinInfo>,
+    pub create_timestamp: i64,
+}
+
 async fn update_coin_info(
     conn: &mut SqliteConnection,
     device_id: i64,
@@ -121,9 +146,34 @@ fn contain_tpye(infos: &[TableCoinInfo], t: u8) -> bool {
     false
 }
 
-pub async fn update_info(device_id: i64, infos: &[TableCoinInfo]) -> Result<(), SqlxErr> {
+async fn insert_history(
+    conn: &mut SqliteConnection,
+    device_id: i64,
+    reason: u8,
+    infos: &[TableCoinInfo],
+) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        INSERT INTO tb_coin_info_history 
+        (device_id, reason, infos, create_timestamp) 
+        VALUES (?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(reason)
+    .bind(serde_cbor::to_vec(&infos).unwrap())
+    .bind(current_timestamp())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_info(device_id: i64, reason: u8, infos: &[TableCoinInfo]) -> Result<(), SqlxErr> {
     let mut tx = get_pool().begin().await?;
 
+    insert_history(&mut tx, device_id, reason, infos).await?;
+
     let all = all_type(&mut *tx, device_id).await?;
 
     for info in infos {
@@ -154,6 +204,19 @@ pub async fn set_type_mask(device_id: i64, type_mask: u32) -> Result<(), SqlxErr
     Ok(())
 }
 
+pub async fn set_applied_mask(device_id: i64, applied_mask: u32) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_coin SET applied_mask = ? WHERE device_id = ?
+    "#,
+    )
+    .bind(applied_mask)
+    .bind(device_id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
 pub async fn update(device_id: i64, model: &str, version: &str, serial_number: &str) -> Result<(), SqlxErr> {
     sqlx::query(
         r#"
@@ -191,7 +254,7 @@ pub async fn create(conn: &mut SqliteConnection, device_id: i64) -> Result<(), S
 pub async fn get(device_id: i64) -> Result<TableCoin, SqlxErr> {
     let row = sqlx::query(
         r#"
-        SELECT id, device_id, type_mask, serial_number, model, version 
+        SELECT id, device_id, type_mask, applied_mask, serial_number, model, version 
         FROM tb_coin WHERE device_id = ?
     "#,
     )
@@ -203,9 +266,10 @@ pub async fn get(device_id: i64) -> Result<TableCoin, SqlxErr> {
         id: row.get(0),
         device_id: row.get(1),
         type_mask: row.get(2),
-        serial_number: row.get(3),
-        model: row.get(4),
-        version: row.get(5),
+        applied_mask: row.get(3),
+        serial_number: row.get(4),
+        model: row.get(5),
+        version: row.get(6),
     };
 
     Ok(coin)
@@ -239,8 +303,47 @@ pub async fn get_info(device_id: i64) -> Result<TableCoinInfos, SqlxErr> {
     Ok(info)
 }
 
+pub async fn select_info_history(
+    device_id: i64,
+    start_timestamp: i64,
+    end_timestamp: i64,
+) -> Result<Array<TableCoinInfoHistory>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, reason, infos, create_timestamp 
+        FROM tb_coin_info_history 
+        WHERE device_id = ? AND create_timestamp >= ? AND create_timestamp <= ? 
+        ORDER BY create_timestamp
+    "#,
+    )
+    .bind(device_id)
+    .bind(start_timestamp)
+    .bind(end_timestamp)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<TableCoinInfoHistory> = rows
+        .iter()
+        .map(|row| {
+            let infos: Vec<u8> = row.get(2);
+            TableCoinInfoHistory {
+                id: row.get(0),
+                reason: row.get(1),
+                infos: serde_cbor::from_slice(&infos).unwrap_or_default(),
+                create_timestamp: row.get(3),
+            }
+        })
+        .collect();
+
+    Ok(vec.into_boxed_slice())
+}
+
 pub async fn init() {
     get_pool().execute(COIN_CREATE_SQL).await.unwrap();
+    add_column("tb_coin", "applied_mask", "INTEGER NOT NULL DEFAULT 0").await;
 
     get_pool().execute(COIN_INFO_CREATE_SQL).await.unwrap();
+
+    get_pool().execute(COIN_INFO_HISTORY_CREATE_SQL).await.unwrap();
+    get_pool().execute(COIN_INFO_HISTORY_INDEX_SQL).await.unwrap();
 }
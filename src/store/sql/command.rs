@@ -0,0 +1,120 @@
+use serde::Serialize;
+use sqlx::{Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_command_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        kind TEXT NOT NULL, 
+        args TEXT NOT NULL, 
+        operator TEXT NOT NULL, 
+        reason TEXT NOT NULL, 
+        state INTEGER NOT NULL, 
+        err_msg TEXT, 
+        create_timestamp INTEGER NOT NULL, 
+        finish_timestamp INTEGER NOT NULL
+    )
+"#;
+
+pub mod state {
+    pub const PENDING: i32 = 0;
+    pub const SUCC: i32 = 1;
+    pub const FAIL: i32 = 2;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableCommandLog {
+    pub id: i64,
+    pub device_id: i64,
+    pub kind: String,
+    pub args: String,
+    pub operator: String,
+    pub reason: String,
+    pub state: i32,
+    pub err_msg: Option<String>,
+    pub create_timestamp: i64,
+    pub finish_timestamp: i64,
+}
+
+pub async fn begin(
+    device_id: i64,
+    kind: &str,
+    args: &str,
+    operator: &str,
+    reason: &str,
+) -> Result<i64, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_command_log 
+        (device_id, kind, args, operator, reason, state, create_timestamp, finish_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(kind)
+    .bind(args)
+    .bind(operator)
+    .bind(reason)
+    .bind(state::PENDING)
+    .bind(current_timestamp())
+    .bind(0)
+    .execute(get_pool())
+    .await?;
+
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn finish(id: i64, err_msg: Option<&str>) -> Result<(), SqlxErr> {
+    let state = if err_msg.is_none() { state::SUCC } else { state::FAIL };
+    sqlx::query(
+        r#"
+        UPDATE tb_command_log SET state = ?, err_msg = ?, finish_timestamp = ? WHERE id = ?
+    "#,
+    )
+    .bind(state)
+    .bind(err_msg)
+    .bind(current_timestamp())
+    .bind(id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn select(device_id: i64) -> Result<Array<TableCommandLog>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT 
+        id, device_id, kind, args, operator, reason, state, err_msg, create_timestamp, finish_timestamp 
+        FROM tb_command_log WHERE device_id = ? ORDER BY id DESC
+    "#,
+    )
+    .bind(device_id)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<TableCommandLog> = rows
+        .iter()
+        .map(|row| TableCommandLog {
+            id: row.get(0),
+            device_id: row.get(1),
+            kind: row.get(2),
+            args: row.get(3),
+            operator: row.get(4),
+            reason: row.get(5),
+            state: row.get(6),
+            err_msg: row.get(7),
+            create_timestamp: row.get(8),
+            finish_timestamp: row.get(9),
+        })
+        .collect();
+
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+}
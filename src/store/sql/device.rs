@@ -1,12 +1,12 @@
 use serde::Serialize;
-use sqlx::{Executor, Row, SqliteConnection};
+use sqlx::{sqlite::SqliteRow, Executor, QueryBuilder, Row, Sqlite, SqliteConnection};
 
 use crate::{
     error::SqlxErr,
     utils::{current_timestamp, Array},
 };
 
-use super::{bill, coin, get_pool};
+use super::{add_column, bill, coin, get_pool};
 
 const CREATE_SQL: &'static str = r#"
     CREATE TABLE IF NOT EXISTS tb_device (
@@ -17,12 +17,29 @@ const CREATE_SQL: &'static str = r#"
         mcu_version TEXT NOT NULL, 
         app_version TEXT NOT NULL,
         address TEXT NOT NULL, 
+        clock_offset INTEGER NOT NULL DEFAULT 0, 
+        clock_sync_timestamp INTEGER NOT NULL DEFAULT 0, 
+        coin_inhibit INTEGER NOT NULL DEFAULT 0, 
+        coin_inhibit_until INTEGER, 
+        bill_inhibit INTEGER NOT NULL DEFAULT 0, 
+        bill_inhibit_until INTEGER, 
         UNIQUE(mac_addr)
     )
 "#;
 
 pub async fn init() {
     get_pool().execute(CREATE_SQL).await.unwrap();
+    add_column("tb_device", "clock_offset", "INTEGER NOT NULL DEFAULT 0").await;
+    add_column("tb_device", "clock_sync_timestamp", "INTEGER NOT NULL DEFAULT 0").await;
+    add_column("tb_device", "coin_inhibit", "INTEGER NOT NULL DEFAULT 0").await;
+    add_column("tb_device", "coin_inhibit_until", "INTEGER").await;
+    add_column("tb_device", "bill_inhibit", "INTEGER NOT NULL DEFAULT 0").await;
+    add_column("tb_device", "bill_inhibit_until", "INTEGER").await;
+}
+
+pub mod peripheral {
+    pub const COIN: u8 = 0;
+    pub const BILL: u8 = 1;
 }
 
 #[derive(Debug, Serialize)]
@@ -34,9 +51,43 @@ pub struct TableDevice {
     pub mcu_version: String,
     pub app_version: String,
     pub address: String,
+    // 设备时钟 - 服务器时钟, 毫秒
+    pub clock_offset: i64,
+    pub clock_sync_timestamp: i64,
+    // 临时禁止收币/收钞, until 为空表示需手动恢复
+    pub coin_inhibit: bool,
+    pub coin_inhibit_until: Option<i64>,
+    pub bill_inhibit: bool,
+    pub bill_inhibit_until: Option<i64>,
 }
 
-async fn create(
+const SELECT_SQL: &str = r#"
+    SELECT 
+    id, name, create_timestamp, mac_addr, mcu_version, app_version, address, 
+    clock_offset, clock_sync_timestamp, 
+    coin_inhibit, coin_inhibit_until, bill_inhibit, bill_inhibit_until
+    FROM tb_device
+"#;
+
+fn to_device(row: &SqliteRow) -> TableDevice {
+    TableDevice {
+        id: row.get(0),
+        name: row.get(1),
+        create_timestamp: row.get(2),
+        mac_addr: row.get(3),
+        mcu_version: row.get(4),
+        app_version: row.get(5),
+        address: row.get(6),
+        clock_offset: row.get(7),
+        clock_sync_timestamp: row.get(8),
+        coin_inhibit: row.get(9),
+        coin_inhibit_until: row.get(10),
+        bill_inhibit: row.get(11),
+        bill_inhibit_until: row.get(12),
+    }
+}
+
+pub(super) async fn create(
     conn: &mut SqliteConnection,
     mac_addr: &str,
     name: &str,
@@ -73,82 +124,146 @@ pub async fn create_by(mac_addr: &str, name: &str, address: &str) -> Result<i64,
     Ok(id)
 }
 
-pub async fn create_if_not_exists(mac_addr: &str) -> Result<i64, SqlxErr> {
-    let mut tx = get_pool().begin().await?;
-
-    let ret = sqlx::query("SELECT id FROM tb_device WHERE mac_addr = ? LIMIT 1")
+pub async fn get_id_by_mac(mac_addr: &str) -> Result<Option<i64>, SqlxErr> {
+    let row = sqlx::query("SELECT id FROM tb_device WHERE mac_addr = ? LIMIT 1")
         .bind(mac_addr)
-        .fetch_one(&mut *tx)
-        .await;
-
-    match ret {
-        Ok(ret) => {
-            let id = ret.get(0);
-            tx.commit().await?;
-            return Ok(id);
-        }
-        Err(sqlx::Error::RowNotFound) => {}
-        Err(e) => {
-            return Err(e);
-        }
-    };
+        .fetch_optional(get_pool())
+        .await?;
+    Ok(row.map(|row| row.get(0)))
+}
 
-    let id = create(&mut *tx, mac_addr, "未命名设备", "未知地址").await?;
+pub async fn get(id: i64) -> Result<TableDevice, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_device(&row))
+}
 
-    tx.commit().await?;
+pub mod sort {
+    pub const ID: u8 = 0;
+    pub const NAME: u8 = 1;
+    pub const CREATE_TIMESTAMP: u8 = 2;
+    pub const APP_VERSION: u8 = 3;
+    pub const MCU_VERSION: u8 = 4;
+}
 
-    Ok(id)
+// 字符串条件中 name 和 address 为子串匹配, 其余为精确匹配
+#[derive(Debug, Default)]
+pub struct DeviceFilter<'a> {
+    pub name: Option<&'a str>,
+    pub address: Option<&'a str>,
+    pub mac_addr: Option<&'a str>,
+    pub app_version: Option<&'a str>,
+    pub mcu_version: Option<&'a str>,
+    pub create_start: Option<i64>,
+    pub create_end: Option<i64>,
+    // 在线状态由调用方换算为设备id列表
+    pub include_ids: Option<&'a [i64]>,
+    pub exclude_ids: Option<&'a [i64]>,
 }
 
-pub async fn get(id: i64) -> Result<TableDevice, SqlxErr> {
-    let row = sqlx::query(
-        r#"
-        SELECT 
-        id, name, create_timestamp, mac_addr, mcu_version, app_version, address
-        FROM tb_device WHERE id = ?
-        "#,
-    )
-    .bind(id)
-    .fetch_one(get_pool())
-    .await?;
+#[derive(Debug, Serialize)]
+pub struct DevicePage {
+    pub total: i64,
+    pub items: Array<TableDevice>,
+}
 
-    let device = TableDevice {
-        id: row.get(0),
-        name: row.get(1),
-        create_timestamp: row.get(2),
-        mac_addr: row.get(3),
-        mcu_version: row.get(4),
-        app_version: row.get(5),
-        address: row.get(6),
+fn push_filter<'a>(qb: &mut QueryBuilder<'a, Sqlite>, filter: &DeviceFilter<'a>) {
+    qb.push(" WHERE 1 = 1");
+    if let Some(name) = filter.name {
+        qb.push(" AND instr(name, ").push_bind(name).push(") > 0");
+    }
+    if let Some(address) = filter.address {
+        qb.push(" AND instr(address, ").push_bind(address).push(") > 0");
+    }
+    if let Some(mac_addr) = filter.mac_addr {
+        qb.push(" AND mac_addr = ").push_bind(mac_addr);
+    }
+    if let Some(app_version) = filter.app_version {
+        qb.push(" AND app_version = ").push_bind(app_version);
+    }
+    if let Some(mcu_version) = filter.mcu_version {
+        qb.push(" AND mcu_version = ").push_bind(mcu_version);
+    }
+    if let Some(start) = filter.create_start {
+        qb.push(" AND create_timestamp >= ").push_bind(start);
+    }
+    if let Some(end) = filter.create_end {
+        qb.push(" AND create_timestamp < ").push_bind(end);
+    }
+    if let Some(ids) = filter.include_ids {
+        if ids.is_empty() {
+            qb.push(" AND 1 = 0");
+        } else {
+            qb.push(" AND id IN (");
+            let mut sep = qb.separated(", ");
+            for id in ids {
+                sep.push_bind(*id);
+            }
+            qb.push(")");
+        }
+    }
+    if let Some(ids) = filter.exclude_ids {
+        if !ids.is_empty() {
+            qb.push(" AND id NOT IN (");
+            let mut sep = qb.separated(", ");
+            for id in ids {
+                sep.push_bind(*id);
+            }
+            qb.push(")");
+        }
+    }
+}
+
+pub async fn select(
+    filter: &DeviceFilter<'_>,
+    sort: u8,
+    desc: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<DevicePage, SqlxErr> {
+    let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM tb_device");
+    push_filter(&mut qb, filter);
+    let total: i64 = qb.build().fetch_one(get_pool()).await?.get(0);
+
+    let column = match sort {
+        sort::NAME => "name",
+        sort::CREATE_TIMESTAMP => "create_timestamp",
+        sort::APP_VERSION => "app_version",
+        sort::MCU_VERSION => "mcu_version",
+        _ => "id",
     };
+    let order = if desc { "DESC" } else { "ASC" };
 
-    Ok(device)
-}
+    let mut qb = QueryBuilder::new(SELECT_SQL);
+    push_filter(&mut qb, filter);
+    // 以 id 作为次序键保证分页稳定
+    qb.push(format!(" ORDER BY {} {}, id {}", column, order, order));
+    qb.push(" LIMIT ").push_bind(limit);
+    qb.push(" OFFSET ").push_bind(offset);
+    let rows = qb.build().fetch_all(get_pool()).await?;
+    let items: Vec<TableDevice> = rows.iter().map(to_device).collect();
 
-pub async fn select() -> Result<Array<TableDevice>, SqlxErr> {
-    let rows = sqlx::query(
-        r#"
-        SELECT 
-        id, name, create_timestamp, mac_addr, mcu_version, app_version, address
-        FROM tb_device
-        "#,
-    )
-    .fetch_all(get_pool())
-    .await?;
+    Ok(DevicePage {
+        total,
+        items: items.into_boxed_slice(),
+    })
+}
 
-    let vec: Vec<TableDevice> = rows
-        .iter()
-        .map(|row| TableDevice {
-            id: row.get(0),
-            name: row.get(1),
-            create_timestamp: row.get(2),
-            mac_addr: row.get(3),
-            mcu_version: row.get(4),
-            app_version: row.get(5),
-            address: row.get(6),
-        })
-        .collect();
+pub async fn select_id_by_app_version(app_version: &str) -> Result<Array<i64>, SqlxErr> {
+    let rows = sqlx::query("SELECT id FROM tb_device WHERE app_version = ?")
+        .bind(app_version)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
 
+pub async fn select_id_by_mcu_version(mcu_version: &str) -> Result<Array<i64>, SqlxErr> {
+    let rows = sqlx::query("SELECT id FROM tb_device WHERE mcu_version = ?")
+        .bind(mcu_version)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
     Ok(vec.into_boxed_slice())
 }
 
@@ -163,7 +278,7 @@ pub async fn delete(id: i64) -> Result<(), SqlxErr> {
 
 pub async fn set_mac_addr(id: i64, mac_addr: Option<&str>) -> Result<(), SqlxErr> {
     if let Some(val) = mac_addr {
-        sqlx::query("UPDATE ta_device SET mac_addr = ? WHERE id = ?")
+        sqlx::query("UPDATE tb_device SET mac_addr = ? WHERE id = ?")
             .bind(val)
             .bind(id)
             .execute(get_pool())
@@ -174,7 +289,7 @@ pub async fn set_mac_addr(id: i64, mac_addr: Option<&str>) -> Result<(), SqlxErr
 
 pub async fn set_name(id: i64, name: Option<&str>) -> Result<(), SqlxErr> {
     if let Some(val) = name {
-        sqlx::query("UPDATE ta_device SET name = ? WHERE id = ?")
+        sqlx::query("UPDATE tb_device SET name = ? WHERE id = ?")
             .bind(val)
             .bind(id)
             .execute(get_pool())
@@ -185,7 +300,7 @@ pub async fn set_name(id: i64, name: Option<&str>) -> Result<(), SqlxErr> {
 
 pub async fn set_address(id: i64, address: Option<&str>) -> Result<(), SqlxErr> {
     if let Some(val) = address {
-        sqlx::query("UPDATE ta_device SET address = ? WHERE id = ?")
+        sqlx::query("UPDATE tb_device SET address = ? WHERE id = ?")
             .bind(val)
             .bind(id)
             .execute(get_pool())
@@ -195,7 +310,7 @@ pub async fn set_address(id: i64, address: Option<&str>) -> Result<(), SqlxErr>
 }
 
 pub async fn set_muc_version(id: i64, mcu_version: &str) -> Result<(), SqlxErr> {
-    sqlx::query(r#"UPDATE SET tb_device mcu_version = ? WHERE id = ?"#)
+    sqlx::query("UPDATE tb_device SET mcu_version = ? WHERE id = ?")
         .bind(mcu_version)
         .bind(id)
         .execute(get_pool())
@@ -204,10 +319,59 @@ pub async fn set_muc_version(id: i64, mcu_version: &str) -> Result<(), SqlxErr>
 }
 
 pub async fn set_app_version(id: i64, app_version: &str) -> Result<(), SqlxErr> {
-    sqlx::query("UPDATE SET tb_device app_version = ? WHERE id = ?")
+    sqlx::query("UPDATE tb_device SET app_version = ? WHERE id = ?")
         .bind(app_version)
         .bind(id)
         .execute(get_pool())
         .await?;
     Ok(())
 }
+
+pub async fn set_clock_offset(id: i64, clock_offset: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_device SET clock_offset = ?, clock_sync_timestamp = ? WHERE id = ?")
+        .bind(clock_offset)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+fn inhibit_columns(peripheral: u8) -> (&'static str, &'static str) {
+    match peripheral {
+        peripheral::BILL => ("bill_inhibit", "bill_inhibit_until"),
+        _ => ("coin_inhibit", "coin_inhibit_until"),
+    }
+}
+
+pub async fn set_inhibit(
+    id: i64,
+    peripheral: u8,
+    inhibit: bool,
+    until: Option<i64>,
+) -> Result<(), SqlxErr> {
+    let (inhibit_col, until_col) = inhibit_columns(peripheral);
+    let sql = format!(
+        "UPDATE tb_device SET {} = ?, {} = ? WHERE id = ?",
+        inhibit_col, until_col
+    );
+    sqlx::query(&sql)
+        .bind(inhibit)
+        .bind(until)
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+// 自动恢复时间已到但仍处于禁止状态的设备
+pub async fn select_inhibit_expired(peripheral: u8, now: i64) -> Result<Array<i64>, SqlxErr> {
+    let (inhibit_col, until_col) = inhibit_columns(peripheral);
+    let sql = format!(
+        "SELECT id FROM tb_device WHERE {} = 1 AND {} IS NOT NULL AND {} <= ?",
+        inhibit_col, until_col, until_col
+    );
+    let rows = sqlx::query(&sql).bind(now).fetch_all(get_pool()).await?;
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
@@ -0,0 +1,173 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_device_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        name TEXT NOT NULL, 
+        since_timestamp INTEGER NOT NULL, 
+        size INTEGER NOT NULL, 
+        received INTEGER NOT NULL, 
+        state INTEGER NOT NULL, 
+        create_timestamp INTEGER NOT NULL, 
+        finish_timestamp INTEGER NOT NULL
+    )
+"#;
+
+pub mod state {
+    pub const UPLOADING: i32 = 0;
+    pub const COMPLETE: i32 = 1;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableDeviceLog {
+    pub id: i64,
+    pub device_id: i64,
+    pub name: String,
+    pub since_timestamp: i64,
+    pub size: i64,
+    pub received: i64,
+    pub state: i32,
+    pub create_timestamp: i64,
+    pub finish_timestamp: i64,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT 
+    id, device_id, name, since_timestamp, size, received, state, create_timestamp, finish_timestamp 
+    FROM tb_device_log
+"#;
+
+fn to_log(row: &SqliteRow) -> TableDeviceLog {
+    TableDeviceLog {
+        id: row.get(0),
+        device_id: row.get(1),
+        name: row.get(2),
+        since_timestamp: row.get(3),
+        size: row.get(4),
+        received: row.get(5),
+        state: row.get(6),
+        create_timestamp: row.get(7),
+        finish_timestamp: row.get(8),
+    }
+}
+
+// 该设备正在上传的日志数达到 max_uploading 时不创建, 返回 None
+pub async fn create(
+    device_id: i64,
+    name: &str,
+    since_timestamp: i64,
+    size: i64,
+    max_uploading: i64,
+) -> Result<Option<i64>, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_device_log 
+        (device_id, name, since_timestamp, size, received, state, create_timestamp, finish_timestamp) 
+        SELECT ?, ?, ?, ?, ?, ?, ?, ? 
+        WHERE (SELECT COUNT(*) FROM tb_device_log WHERE device_id = ? AND state = ?) < ?
+    "#,
+    )
+    .bind(device_id)
+    .bind(name)
+    .bind(since_timestamp)
+    .bind(size)
+    .bind(0)
+    .bind(state::UPLOADING)
+    .bind(current_timestamp())
+    .bind(0)
+    .bind(device_id)
+    .bind(state::UPLOADING)
+    .bind(max_uploading)
+    .execute(get_pool())
+    .await?;
+
+    if ret.rows_affected() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(ret.last_insert_rowid()))
+}
+
+pub async fn set_received(id: i64, received: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_device_log SET received = ? WHERE id = ?")
+        .bind(received)
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn set_complete(id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_device_log SET state = ?, finish_timestamp = ? WHERE id = ?")
+        .bind(state::COMPLETE)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn get(id: i64) -> Result<TableDeviceLog, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_log(&row))
+}
+
+pub async fn select(device_id: i64) -> Result<Array<TableDeviceLog>, SqlxErr> {
+    let sql = format!("{} WHERE device_id = ? ORDER BY id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(device_id)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<TableDeviceLog> = rows.iter().map(to_log).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 超过保留时间或数量的日志, 以及 stale_before 之前开始且仍未完成的上传
+// 数量只统计已完成的, 正在上传的不会因数量被删除
+pub async fn select_expired(
+    device_id: i64,
+    max_count: i64,
+    before_timestamp: i64,
+    stale_before: i64,
+) -> Result<Array<i64>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id FROM tb_device_log WHERE device_id = ? AND (
+            create_timestamp < ? OR (state = ? AND create_timestamp < ?) OR (state = ? AND id NOT IN (
+                SELECT id FROM tb_device_log WHERE device_id = ? AND state = ? ORDER BY id DESC LIMIT ?
+            ))
+        )
+    "#,
+    )
+    .bind(device_id)
+    .bind(before_timestamp)
+    .bind(state::UPLOADING)
+    .bind(stale_before)
+    .bind(state::COMPLETE)
+    .bind(device_id)
+    .bind(state::COMPLETE)
+    .bind(max_count)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn delete(id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("DELETE FROM tb_device_log WHERE id = ?")
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_diagnostic (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        items INTEGER NOT NULL, 
+        operator TEXT NOT NULL, 
+        report BLOB, 
+        err_msg TEXT, 
+        create_timestamp INTEGER NOT NULL
+    )
+"#;
+
+pub mod item {
+    pub const COIN: u8 = 0x01;
+    pub const BILL: u8 = 0x02;
+    pub const MDB: u8 = 0x04;
+}
+
+// status 0 表示正常, 其余为设备定义的故障码
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TubeStatus {
+    pub coin_type: u8,
+    pub coin_count: u16,
+    pub status: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoinTest {
+    pub status: u8,
+    pub tubes: Array<TubeStatus>,
+    pub err_msg: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillTest {
+    pub status: u8,
+    pub stacker_full: bool,
+    pub err_msg: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MdbScan {
+    pub addrs: Array<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub coin: Option<CoinTest>,
+    pub bill: Option<BillTest>,
+    pub mdb: Option<MdbScan>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableDiagnostic {
+    pub id: i64,
+    pub device_id: i64,
+    pub items: u8,
+    pub operator: String,
+    pub report: Option<SelfTestReport>,
+    pub err_msg: Option<String>,
+    pub create_timestamp: i64,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT id, device_id, items, operator, report, err_msg, create_timestamp FROM tb_diagnostic
+"#;
+
+fn to_diagnostic(row: &SqliteRow) -> TableDiagnostic {
+    let report: Option<Vec<u8>> = row.get(4);
+    TableDiagnostic {
+        id: row.get(0),
+        device_id: row.get(1),
+        items: row.get(2),
+        operator: row.get(3),
+        report: report.and_then(|v| serde_cbor::from_slice(&v).ok()),
+        err_msg: row.get(5),
+        create_timestamp: row.get(6),
+    }
+}
+
+pub async fn create(
+    device_id: i64,
+    items: u8,
+    operator: &str,
+    report: Result<&SelfTestReport, &str>,
+) -> Result<i64, SqlxErr> {
+    let (report, err_msg) = match report {
+        Ok(v) => (Some(serde_cbor::to_vec(v).unwrap()), None),
+        Err(e) => (None, Some(e)),
+    };
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_diagnostic 
+        (device_id, items, operator, report, err_msg, create_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(items)
+    .bind(operator)
+    .bind(report)
+    .bind(err_msg)
+    .bind(current_timestamp())
+    .execute(get_pool())
+    .await?;
+
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn get(id: i64) -> Result<TableDiagnostic, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_diagnostic(&row))
+}
+
+pub async fn select(device_id: i64) -> Result<Array<TableDiagnostic>, SqlxErr> {
+    let sql = format!("{} WHERE device_id = ? ORDER BY id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(device_id)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<TableDiagnostic> = rows.iter().map(to_diagnostic).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+}
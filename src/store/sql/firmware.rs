@@ -0,0 +1,267 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::get_pool;
+
+const FIRMWARE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_firmware (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        target INTEGER NOT NULL, 
+        version TEXT NOT NULL, 
+        hash TEXT NOT NULL, 
+        size INTEGER NOT NULL, 
+        create_timestamp INTEGER NOT NULL, 
+        UNIQUE(target, version)
+    )
+"#;
+
+const UPDATE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_firmware_update (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        firmware_id INTEGER NOT NULL, 
+        target INTEGER NOT NULL, 
+        state INTEGER NOT NULL, 
+        offset INTEGER NOT NULL, 
+        err_msg TEXT, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL
+    )
+"#;
+
+pub mod target {
+    pub const APP: i32 = 0;
+    pub const MCU: i32 = 1;
+}
+
+pub mod state {
+    pub const PENDING: i32 = 0;
+    pub const DOWNLOADING: i32 = 1;
+    pub const INSTALLING: i32 = 2;
+    pub const SUCCEEDED: i32 = 3;
+    pub const FAILED: i32 = 4;
+    // 被新的升级取代或发布计划取消, 不计入失败
+    pub const CANCELLED: i32 = 5;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableFirmware {
+    pub id: i64,
+    pub target: i32,
+    pub version: String,
+    pub hash: String,
+    pub size: i64,
+    pub create_timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableFirmwareUpdate {
+    pub id: i64,
+    pub device_id: i64,
+    pub firmware_id: i64,
+    pub target: i32,
+    pub version: String,
+    pub state: i32,
+    pub offset: i64,
+    pub err_msg: Option<String>,
+    pub create_timestamp: i64,
+    pub update_timestamp: i64,
+}
+
+const FIRMWARE_SELECT_SQL: &str = r#"
+    SELECT id, target, version, hash, size, create_timestamp FROM tb_firmware
+"#;
+
+const UPDATE_SELECT_SQL: &str = r#"
+    SELECT 
+    u.id, u.device_id, u.firmware_id, u.target, f.version, u.state, u.offset, u.err_msg, 
+    u.create_timestamp, u.update_timestamp 
+    FROM tb_firmware_update u JOIN tb_firmware f ON u.firmware_id = f.id
+"#;
+
+fn to_firmware(row: &SqliteRow) -> TableFirmware {
+    TableFirmware {
+        id: row.get(0),
+        target: row.get(1),
+        version: row.get(2),
+        hash: row.get(3),
+        size: row.get(4),
+        create_timestamp: row.get(5),
+    }
+}
+
+fn to_update(row: &SqliteRow) -> TableFirmwareUpdate {
+    TableFirmwareUpdate {
+        id: row.get(0),
+        device_id: row.get(1),
+        firmware_id: row.get(2),
+        target: row.get(3),
+        version: row.get(4),
+        state: row.get(5),
+        offset: row.get(6),
+        err_msg: row.get(7),
+        create_timestamp: row.get(8),
+        update_timestamp: row.get(9),
+    }
+}
+
+pub async fn create(target: i32, version: &str, hash: &str, size: i64) -> Result<i64, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_firmware 
+        (target, version, hash, size, create_timestamp) 
+        VALUES (?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(target)
+    .bind(version)
+    .bind(hash)
+    .bind(size)
+    .bind(current_timestamp())
+    .execute(get_pool())
+    .await?;
+
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn get(id: i64) -> Result<TableFirmware, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", FIRMWARE_SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_firmware(&row))
+}
+
+pub async fn select() -> Result<Array<TableFirmware>, SqlxErr> {
+    let sql = format!("{} ORDER BY id DESC", FIRMWARE_SELECT_SQL);
+    let rows = sqlx::query(&sql).fetch_all(get_pool()).await?;
+    let vec: Vec<TableFirmware> = rows.iter().map(to_firmware).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 同一设备同一目标未完成的升级会被新的升级取代
+pub async fn create_update(device_id: i64, firmware: &TableFirmware) -> Result<i64, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    let now = current_timestamp();
+
+    sqlx::query(
+        r#"
+        UPDATE tb_firmware_update SET state = ?, err_msg = ?, update_timestamp = ? 
+        WHERE device_id = ? AND target = ? AND state < ?
+    "#,
+    )
+    .bind(state::CANCELLED)
+    .bind("superseded")
+    .bind(now)
+    .bind(device_id)
+    .bind(firmware.target)
+    .bind(state::SUCCEEDED)
+    .execute(&mut *tx)
+    .await?;
+
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_firmware_update 
+        (device_id, firmware_id, target, state, offset, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(firmware.id)
+    .bind(firmware.target)
+    .bind(state::PENDING)
+    .bind(0)
+    .bind(now)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn get_update(id: i64) -> Result<TableFirmwareUpdate, SqlxErr> {
+    let sql = format!("{} WHERE u.id = ?", UPDATE_SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_update(&row))
+}
+
+pub async fn select_update(device_id: i64) -> Result<Array<TableFirmwareUpdate>, SqlxErr> {
+    let sql = format!("{} WHERE u.device_id = ? ORDER BY u.id DESC", UPDATE_SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(device_id)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<TableFirmwareUpdate> = rows.iter().map(to_update).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn select_update_by_state(device_id: i64, state: i32) -> Result<Array<TableFirmwareUpdate>, SqlxErr> {
+    let sql = format!("{} WHERE u.device_id = ? AND u.state = ?", UPDATE_SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(device_id)
+        .bind(state)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<TableFirmwareUpdate> = rows.iter().map(to_update).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn set_update_state(id: i64, state: i32, err_msg: Option<&str>) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_firmware_update SET state = ?, err_msg = ?, update_timestamp = ? WHERE id = ?
+    "#,
+    )
+    .bind(state)
+    .bind(err_msg)
+    .bind(current_timestamp())
+    .bind(id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+// 只有等待或下载中的升级可以开始下载, 已取消或失败的返回 false
+pub async fn start_download(id: i64) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query(
+        "UPDATE tb_firmware_update SET state = ?, update_timestamp = ? WHERE id = ? AND state IN (?, ?)",
+    )
+    .bind(state::DOWNLOADING)
+    .bind(current_timestamp())
+    .bind(id)
+    .bind(state::PENDING)
+    .bind(state::DOWNLOADING)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+// 下载期间被取消或标记失败时返回 false
+pub async fn finish_download(id: i64) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query(
+        "UPDATE tb_firmware_update SET state = ?, update_timestamp = ? WHERE id = ? AND state = ?",
+    )
+    .bind(state::INSTALLING)
+    .bind(current_timestamp())
+    .bind(id)
+    .bind(state::DOWNLOADING)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+pub async fn set_update_offset(id: i64, offset: i64) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_firmware_update SET offset = ?, update_timestamp = ? WHERE id = ?")
+        .bind(offset)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(FIRMWARE_CREATE_SQL).await.unwrap();
+    get_pool().execute(UPDATE_CREATE_SQL).await.unwrap();
+}
@@ -1,12 +1,26 @@
 use crate::{config::SQLITE_PATH, error::SqlxErr};
-use sqlx::SqlitePool;
+use sqlx::{Executor, Row, SqlitePool};
 use std::mem::MaybeUninit;
 
 static mut POOL: MaybeUninit<SqlitePool> = MaybeUninit::uninit();
 
+pub mod api_key;
+pub mod audit;
+pub mod auth;
 pub mod bill;
+pub mod campaign;
 pub mod coin;
+pub mod command;
 pub mod device;
+pub mod device_log;
+pub mod diagnostic;
+pub mod firmware;
+pub mod payout;
+pub mod profile;
+pub mod provision;
+pub mod setting;
+pub mod telemetry;
+pub mod user;
 
 pub async fn sql_init() -> Result<(), SqlxErr> {
     let pool = SqlitePool::connect(SQLITE_PATH).await?;
@@ -18,10 +32,40 @@ pub async fn sql_init() -> Result<(), SqlxErr> {
     device::init().await;
     coin::init().await;
     bill::init().await;
+    payout::init().await;
+    command::init().await;
+    device_log::init().await;
+    firmware::init().await;
+    campaign::init().await;
+    setting::init().await;
+    profile::init().await;
+    telemetry::init().await;
+    diagnostic::init().await;
+    auth::init().await;
+    provision::init().await;
+    user::init().await;
+    api_key::init().await;
+    audit::init().await;
 
     Ok(())
 }
 
+// 旧数据库中缺少的列在启动时补上, 返回本次是否新增
+async fn add_column(table: &str, column: &str, define: &str) -> bool {
+    let sql = format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?", table);
+    let row = sqlx::query(&sql)
+        .bind(column)
+        .fetch_one(get_pool())
+        .await
+        .unwrap();
+    let count: i64 = row.get(0);
+    if count == 0 {
+        let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, define);
+        get_pool().execute(sql.as_str()).await.unwrap();
+    }
+    count == 0
+}
+
 pub fn get_pool() -> &'static SqlitePool {
     unsafe { POOL.assume_init_ref() }
 }
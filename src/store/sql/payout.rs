@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{add_column, get_pool};
+
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_coin_payout (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        request_key TEXT NOT NULL, 
+        req_amount INTEGER, 
+        req_counts BLOB, 
+        state INTEGER NOT NULL, 
+        amount INTEGER NOT NULL, 
+        counts BLOB, 
+        err_code INTEGER NOT NULL DEFAULT -1, 
+        err_msg TEXT, 
+        create_timestamp INTEGER NOT NULL, 
+        finish_timestamp INTEGER NOT NULL, 
+        UNIQUE(request_key)
+    )
+"#;
+
+pub mod state {
+    pub const PENDING: i32 = 0;
+    pub const SUCC: i32 = 1;
+    pub const FAIL: i32 = 2;
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct PayoutCount {
+    pub coin_type: u8,
+    pub count: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TablePayout {
+    pub id: i64,
+    pub device_id: i64,
+    pub request_key: String,
+    pub req_amount: Option<u32>,
+    pub req_counts: Option<Array<PayoutCount>>,
+    pub state: i32,
+    pub amount: u32,
+    pub counts: Option<Array<PayoutCount>>,
+    pub err_code: i32,
+    pub err_msg: Option<String>,
+    pub create_timestamp: i64,
+    pub finish_timestamp: i64,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT 
+    id, device_id, request_key, req_amount, req_counts, state, amount, counts, err_code, err_msg, create_timestamp, finish_timestamp 
+    FROM tb_coin_payout
+"#;
+
+fn decode_counts(row: &SqliteRow, index: usize) -> Option<Array<PayoutCount>> {
+    let buf: Option<Vec<u8>> = row.get(index);
+    buf.and_then(|v| serde_cbor::from_slice(&v).ok())
+}
+
+fn encode_counts(counts: Option<&[PayoutCount]>) -> Option<Vec<u8>> {
+    counts.map(|v| serde_cbor::to_vec(&v).unwrap())
+}
+
+fn to_payout(row: &SqliteRow) -> TablePayout {
+    TablePayout {
+        id: row.get(0),
+        device_id: row.get(1),
+        request_key: row.get(2),
+        req_amount: row.get(3),
+        req_counts: decode_counts(row, 4),
+        state: row.get(5),
+        amount: row.get(6),
+        counts: decode_counts(row, 7),
+        err_code: row.get(8),
+        err_msg: row.get(9),
+        create_timestamp: row.get(10),
+        finish_timestamp: row.get(11),
+    }
+}
+
+// request_key 已存在时返回 None
+pub async fn begin(
+    device_id: i64,
+    request_key: &str,
+    amount: Option<u32>,
+    counts: Option<&[PayoutCount]>,
+) -> Result<Option<i64>, SqlxErr> {
+    let ret = sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO tb_coin_payout 
+        (device_id, request_key, req_amount, req_counts, state, amount, create_timestamp, finish_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(request_key)
+    .bind(amount)
+    .bind(encode_counts(counts))
+    .bind(state::PENDING)
+    .bind(0)
+    .bind(current_timestamp())
+    .bind(0)
+    .execute(get_pool())
+    .await?;
+
+    if ret.rows_affected() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(ret.last_insert_rowid()))
+}
+
+pub async fn set_succ(id: i64, amount: u32, counts: &[PayoutCount]) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_coin_payout SET state = ?, amount = ?, counts = ?, finish_timestamp = ? WHERE id = ?
+    "#,
+    )
+    .bind(state::SUCC)
+    .bind(amount)
+    .bind(encode_counts(Some(counts)))
+    .bind(current_timestamp())
+    .bind(id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn set_fail(id: i64, err_code: i32, err_msg: &str) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        UPDATE tb_coin_payout SET state = ?, err_code = ?, err_msg = ?, finish_timestamp = ? WHERE id = ?
+    "#,
+    )
+    .bind(state::FAIL)
+    .bind(err_code)
+    .bind(err_msg)
+    .bind(current_timestamp())
+    .bind(id)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+pub async fn get_by_key(request_key: &str) -> Result<Option<TablePayout>, SqlxErr> {
+    let sql = format!("{} WHERE request_key = ?", SELECT_SQL);
+    let row = sqlx::query(&sql)
+        .bind(request_key)
+        .fetch_optional(get_pool())
+        .await?;
+    Ok(row.as_ref().map(to_payout))
+}
+
+pub async fn select(device_id: i64) -> Result<Array<TablePayout>, SqlxErr> {
+    let sql = format!("{} WHERE device_id = ? ORDER BY id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(device_id)
+        .fetch_all(get_pool())
+        .await?;
+
+    let vec: Vec<TablePayout> = rows.iter().map(to_payout).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+    add_column("tb_coin_payout", "err_code", "INTEGER NOT NULL DEFAULT -1").await;
+}
@@ -0,0 +1,296 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row, SqliteConnection};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{get_pool, setting::{SettingItem, SettingValue, TableSetting}};
+
+const PROFILE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_profile (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        name TEXT NOT NULL, 
+        coin_mask INTEGER, 
+        bill_mask INTEGER, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL, 
+        UNIQUE(name)
+    )
+"#;
+
+const PROFILE_SETTING_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_profile_setting (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        profile_id INTEGER NOT NULL, 
+        key TEXT NOT NULL, 
+        value_type INTEGER NOT NULL, 
+        value TEXT NOT NULL, 
+        UNIQUE(profile_id, key)
+    )
+"#;
+
+const PROFILE_DEVICE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_profile_device (
+        device_id INTEGER PRIMARY KEY, 
+        profile_id INTEGER NOT NULL, 
+        coin_mask INTEGER, 
+        bill_mask INTEGER
+    )
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct TableProfile {
+    pub id: i64,
+    pub name: String,
+    pub coin_mask: Option<u32>,
+    pub bill_mask: Option<u32>,
+    pub create_timestamp: i64,
+    pub update_timestamp: i64,
+}
+
+// coin_mask/bill_mask 为设备单独覆盖的值
+#[derive(Debug, Serialize)]
+pub struct TableProfileDevice {
+    pub device_id: i64,
+    pub profile_id: i64,
+    pub coin_mask: Option<u32>,
+    pub bill_mask: Option<u32>,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT id, name, coin_mask, bill_mask, create_timestamp, update_timestamp FROM tb_profile
+"#;
+
+fn to_profile(row: &SqliteRow) -> TableProfile {
+    TableProfile {
+        id: row.get(0),
+        name: row.get(1),
+        coin_mask: row.get(2),
+        bill_mask: row.get(3),
+        create_timestamp: row.get(4),
+        update_timestamp: row.get(5),
+    }
+}
+
+async fn insert_settings(
+    conn: &mut SqliteConnection,
+    profile_id: i64,
+    settings: &[SettingItem],
+) -> Result<(), SqlxErr> {
+    for item in settings {
+        let Some(value) = &item.value else {
+            continue;
+        };
+        let (value_type, value) = value.encode();
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO tb_profile_setting 
+            (profile_id, key, value_type, value) 
+            VALUES (?, ?, ?, ?)
+        "#,
+        )
+        .bind(profile_id)
+        .bind(&item.key)
+        .bind(value_type)
+        .bind(value)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn create(
+    name: &str,
+    coin_mask: Option<u32>,
+    bill_mask: Option<u32>,
+    settings: &[SettingItem],
+) -> Result<i64, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    let now = current_timestamp();
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_profile 
+        (name, coin_mask, bill_mask, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(name)
+    .bind(coin_mask)
+    .bind(bill_mask)
+    .bind(now)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+    let id = ret.last_insert_rowid();
+
+    insert_settings(&mut tx, id, settings).await?;
+
+    tx.commit().await?;
+    Ok(id)
+}
+
+// 整体替换方案内容
+pub async fn update(
+    id: i64,
+    name: &str,
+    coin_mask: Option<u32>,
+    bill_mask: Option<u32>,
+    settings: &[SettingItem],
+) -> Result<(), SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    sqlx::query(
+        r#"
+        UPDATE tb_profile SET name = ?, coin_mask = ?, bill_mask = ?, update_timestamp = ? WHERE id = ?
+    "#,
+    )
+    .bind(name)
+    .bind(coin_mask)
+    .bind(bill_mask)
+    .bind(current_timestamp())
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM tb_profile_setting WHERE profile_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    insert_settings(&mut tx, id, settings).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete(id: i64) -> Result<(), SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    for sql in [
+        "DELETE FROM tb_profile WHERE id = ?",
+        "DELETE FROM tb_profile_setting WHERE profile_id = ?",
+        "DELETE FROM tb_profile_device WHERE profile_id = ?",
+    ] {
+        sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get(id: i64) -> Result<TableProfile, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_profile(&row))
+}
+
+pub async fn select() -> Result<Array<TableProfile>, SqlxErr> {
+    let sql = format!("{} ORDER BY id", SELECT_SQL);
+    let rows = sqlx::query(&sql).fetch_all(get_pool()).await?;
+    let vec: Vec<TableProfile> = rows.iter().map(to_profile).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn select_settings(profile_id: i64) -> Result<Array<TableSetting>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT s.key, s.value_type, s.value, p.update_timestamp 
+        FROM tb_profile_setting s JOIN tb_profile p ON s.profile_id = p.id 
+        WHERE s.profile_id = ? ORDER BY s.key
+    "#,
+    )
+    .bind(profile_id)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<TableSetting> = rows
+        .iter()
+        .filter_map(|row| {
+            let value = SettingValue::decode(row.get(1), row.get(2))?;
+            Some(TableSetting {
+                key: row.get(0),
+                value,
+                version: 0,
+                update_timestamp: row.get(3),
+            })
+        })
+        .collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 重新分配时保留设备的覆盖值
+pub async fn assign(profile_id: i64, device_id: i64) -> Result<(), SqlxErr> {
+    let mut conn = get_pool().acquire().await?;
+    assign_by(&mut conn, profile_id, device_id).await
+}
+
+pub(super) async fn assign_by(
+    conn: &mut SqliteConnection,
+    profile_id: i64,
+    device_id: i64,
+) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        INSERT INTO tb_profile_device (device_id, profile_id) VALUES (?, ?) 
+        ON CONFLICT(device_id) DO UPDATE SET profile_id = excluded.profile_id
+    "#,
+    )
+    .bind(device_id)
+    .bind(profile_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn unassign(device_id: i64) -> Result<(), SqlxErr> {
+    sqlx::query("DELETE FROM tb_profile_device WHERE device_id = ?")
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn get_device(device_id: i64) -> Result<Option<TableProfileDevice>, SqlxErr> {
+    let row = sqlx::query(
+        "SELECT device_id, profile_id, coin_mask, bill_mask FROM tb_profile_device WHERE device_id = ?",
+    )
+    .bind(device_id)
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|row| TableProfileDevice {
+        device_id: row.get(0),
+        profile_id: row.get(1),
+        coin_mask: row.get(2),
+        bill_mask: row.get(3),
+    }))
+}
+
+pub async fn select_device_ids(profile_id: i64) -> Result<Array<i64>, SqlxErr> {
+    let rows = sqlx::query("SELECT device_id FROM tb_profile_device WHERE profile_id = ?")
+        .bind(profile_id)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<i64> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 设备未分配配置方案时返回 false
+pub async fn set_coin_override(device_id: i64, coin_mask: Option<u32>) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query("UPDATE tb_profile_device SET coin_mask = ? WHERE device_id = ?")
+        .bind(coin_mask)
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+pub async fn set_bill_override(device_id: i64, bill_mask: Option<u32>) -> Result<bool, SqlxErr> {
+    let ret = sqlx::query("UPDATE tb_profile_device SET bill_mask = ? WHERE device_id = ?")
+        .bind(bill_mask)
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+pub async fn init() {
+    get_pool().execute(PROFILE_CREATE_SQL).await.unwrap();
+    get_pool().execute(PROFILE_SETTING_CREATE_SQL).await.unwrap();
+    get_pool().execute(PROFILE_DEVICE_CREATE_SQL).await.unwrap();
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row, SqliteConnection};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{auth, device, get_pool, profile};
+
+const CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_device_pending (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        mac_addr TEXT NOT NULL, 
+        addr TEXT NOT NULL, 
+        state INTEGER NOT NULL, 
+        attempts INTEGER NOT NULL, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL, 
+        UNIQUE(mac_addr)
+    )
+"#;
+
+pub mod state {
+    pub const PENDING: i32 = 0;
+    pub const REJECTED: i32 = 1;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TablePending {
+    pub id: i64,
+    pub mac_addr: String,
+    // 最后一次登录的地址
+    pub addr: String,
+    pub state: i32,
+    pub attempts: i64,
+    pub create_timestamp: i64,
+    pub update_timestamp: i64,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT id, mac_addr, addr, state, attempts, create_timestamp, update_timestamp 
+    FROM tb_device_pending
+"#;
+
+fn to_pending(row: &SqliteRow) -> TablePending {
+    TablePending {
+        id: row.get(0),
+        mac_addr: row.get(1),
+        addr: row.get(2),
+        state: row.get(3),
+        attempts: row.get(4),
+        create_timestamp: row.get(5),
+        update_timestamp: row.get(6),
+    }
+}
+
+async fn exists_mac(conn: &mut SqliteConnection, mac_addr: &str) -> Result<bool, SqlxErr> {
+    let row = sqlx::query("SELECT COUNT(*) FROM tb_device WHERE mac_addr = ?")
+        .bind(mac_addr)
+        .fetch_one(conn)
+        .await?;
+    Ok(row.get::<i64, _>(0) > 0)
+}
+
+// 已存在时只更新地址和次数, 不改变状态, 返回当前状态
+// 新的 MAC 在待审批数达到上限时返回 None
+pub async fn upsert(mac_addr: &str, addr: &str, max_pending: i64) -> Result<Option<i32>, SqlxErr> {
+    let now = current_timestamp();
+    let mut tx = get_pool().begin().await?;
+    let row = sqlx::query(
+        r#"
+        SELECT 
+        (SELECT COUNT(*) FROM tb_device_pending WHERE mac_addr = ?), 
+        (SELECT COUNT(*) FROM tb_device_pending WHERE state = ?)
+    "#,
+    )
+    .bind(mac_addr)
+    .bind(state::PENDING)
+    .fetch_one(&mut *tx)
+    .await?;
+    let (exists, pending): (i64, i64) = (row.get(0), row.get(1));
+    if exists == 0 && pending >= max_pending {
+        return Ok(None);
+    }
+    let row = sqlx::query(
+        r#"
+        INSERT INTO tb_device_pending 
+        (mac_addr, addr, state, attempts, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, 1, ?, ?) 
+        ON CONFLICT(mac_addr) DO UPDATE SET 
+        addr = excluded.addr, attempts = attempts + 1, update_timestamp = excluded.update_timestamp 
+        RETURNING state
+    "#,
+    )
+    .bind(mac_addr)
+    .bind(addr)
+    .bind(state::PENDING)
+    .bind(now)
+    .bind(now)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(row.get(0)))
+}
+
+// 自动登记: 创建设备并分配临时密钥, 未确认的设备数达到上限时返回 None
+pub async fn auto_approve(
+    mac_addr: &str,
+    secret: &[u8],
+    max_pending: i64,
+) -> Result<Option<i64>, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    if auth::count_provisional(&mut tx).await? >= max_pending {
+        return Ok(None);
+    }
+    let device_id = device::create(&mut tx, mac_addr, "未命名设备", "未知地址").await?;
+    auth::set_secret_by(&mut tx, device_id, secret, true).await?;
+    tx.commit().await?;
+    Ok(Some(device_id))
+}
+
+// MAC 地址已登记为设备时返回 None
+pub async fn approve(
+    pending: &TablePending,
+    name: &str,
+    address: &str,
+    profile_id: Option<i64>,
+    secret: &[u8],
+) -> Result<Option<i64>, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    if exists_mac(&mut tx, &pending.mac_addr).await? {
+        return Ok(None);
+    }
+    let device_id = device::create(&mut tx, &pending.mac_addr, name, address).await?;
+    sqlx::query("DELETE FROM tb_device_pending WHERE id = ?")
+        .bind(pending.id)
+        .execute(&mut *tx)
+        .await?;
+    if let Some(profile_id) = profile_id {
+        profile::assign_by(&mut tx, profile_id, device_id).await?;
+    }
+    auth::set_secret_by(&mut tx, device_id, secret, false).await?;
+    tx.commit().await?;
+    Ok(Some(device_id))
+}
+
+pub async fn get(id: i64) -> Result<TablePending, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_pending(&row))
+}
+
+pub async fn select(state: Option<i32>) -> Result<Array<TablePending>, SqlxErr> {
+    let sql = format!("{} WHERE (? IS NULL OR state = ?) ORDER BY id DESC", SELECT_SQL);
+    let rows = sqlx::query(&sql)
+        .bind(state)
+        .bind(state)
+        .fetch_all(get_pool())
+        .await?;
+    let vec: Vec<TablePending> = rows.iter().map(to_pending).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn set_state(id: i64, state: i32) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_device_pending SET state = ?, update_timestamp = ? WHERE id = ?")
+        .bind(state)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_SQL).await.unwrap();
+}
@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, Executor, Row, SqliteConnection};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, sha256_hex, Array}};
+
+use super::get_pool;
+
+const SETTING_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_setting (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        key TEXT NOT NULL, 
+        value_type INTEGER NOT NULL, 
+        value TEXT NOT NULL, 
+        version INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL, 
+        UNIQUE(device_id, key)
+    )
+"#;
+
+const HISTORY_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_setting_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        key TEXT NOT NULL, 
+        old_value_type INTEGER, 
+        old_value TEXT, 
+        new_value_type INTEGER, 
+        new_value TEXT, 
+        version INTEGER NOT NULL, 
+        create_timestamp INTEGER NOT NULL
+    )
+"#;
+
+const STATE_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_setting_state (
+        device_id INTEGER PRIMARY KEY, 
+        version INTEGER NOT NULL, 
+        applied_version INTEGER NOT NULL, 
+        reported_hash TEXT
+    )
+"#;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl SettingValue {
+    pub(super) fn encode(&self) -> (i32, String) {
+        match self {
+            Self::Bool(v) => (0, v.to_string()),
+            Self::Int(v) => (1, v.to_string()),
+            Self::Float(v) => (2, v.to_string()),
+            Self::Text(v) => (3, v.clone()),
+        }
+    }
+
+    pub(super) fn decode(value_type: i32, value: String) -> Option<Self> {
+        match value_type {
+            0 => value.parse().ok().map(Self::Bool),
+            1 => value.parse().ok().map(Self::Int),
+            2 => value.parse().ok().map(Self::Float),
+            3 => Some(Self::Text(value)),
+            _ => None,
+        }
+    }
+
+    fn decode_opt(value_type: Option<i32>, value: Option<String>) -> Option<Self> {
+        match (value_type, value) {
+            (Some(t), Some(v)) => Self::decode(t, v),
+            _ => None,
+        }
+    }
+}
+
+// value 为 None 表示删除该项
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettingItem {
+    pub key: String,
+    pub value: Option<SettingValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSetting {
+    pub key: String,
+    pub value: SettingValue,
+    pub version: i64,
+    pub update_timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSettingHistory {
+    pub id: i64,
+    pub key: String,
+    pub old_value: Option<SettingValue>,
+    pub new_value: Option<SettingValue>,
+    pub version: i64,
+    pub create_timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSettingState {
+    pub device_id: i64,
+    pub version: i64,
+    pub applied_version: i64,
+    pub reported_hash: Option<String>,
+}
+
+fn to_setting(row: &SqliteRow) -> Option<TableSetting> {
+    let value = SettingValue::decode(row.get(1), row.get(2))?;
+    Some(TableSetting {
+        key: row.get(0),
+        value,
+        version: row.get(3),
+        update_timestamp: row.get(4),
+    })
+}
+
+// 按 key 排序后拼接 "key=type:value\n" 计算 sha256, 设备端使用相同算法
+pub fn hash(settings: &[TableSetting]) -> String {
+    let mut items: Vec<&TableSetting> = settings.iter().collect();
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+    let mut buf = String::new();
+    for item in items {
+        let (value_type, value) = item.value.encode();
+        buf.push_str(&format!("{}={}:{}\n", item.key, value_type, value));
+    }
+    sha256_hex(buf.as_bytes())
+}
+
+async fn get_value(
+    conn: &mut SqliteConnection,
+    device_id: i64,
+    key: &str,
+) -> Result<Option<SettingValue>, SqlxErr> {
+    let row = sqlx::query("SELECT value_type, value FROM tb_setting WHERE device_id = ? AND key = ?")
+        .bind(device_id)
+        .bind(key)
+        .fetch_optional(conn)
+        .await?;
+    Ok(row.and_then(|row| SettingValue::decode(row.get(0), row.get(1))))
+}
+
+async fn get_state_by(conn: &mut SqliteConnection, device_id: i64) -> Result<TableSettingState, SqlxErr> {
+    sqlx::query("INSERT OR IGNORE INTO tb_setting_state (device_id, version, applied_version) VALUES (?, 0, 0)")
+        .bind(device_id)
+        .execute(&mut *conn)
+        .await?;
+    let row = sqlx::query(
+        "SELECT device_id, version, applied_version, reported_hash FROM tb_setting_state WHERE device_id = ?",
+    )
+    .bind(device_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(TableSettingState {
+        device_id: row.get(0),
+        version: row.get(1),
+        applied_version: row.get(2),
+        reported_hash: row.get(3),
+    })
+}
+
+pub async fn get_state(device_id: i64) -> Result<TableSettingState, SqlxErr> {
+    let mut conn = get_pool().acquire().await?;
+    get_state_by(&mut conn, device_id).await
+}
+
+struct Change<'a> {
+    key: &'a str,
+    old: Option<&'a SettingValue>,
+    new: Option<&'a SettingValue>,
+}
+
+async fn insert_history(
+    conn: &mut SqliteConnection,
+    device_id: i64,
+    change: &Change<'_>,
+    version: i64,
+    now: i64,
+) -> Result<(), SqlxErr> {
+    let old = change.old.map(|v| v.encode());
+    let new = change.new.map(|v| v.encode());
+    sqlx::query(
+        r#"
+        INSERT INTO tb_setting_history 
+        (device_id, key, old_value_type, old_value, new_value_type, new_value, version, create_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(device_id)
+    .bind(change.key)
+    .bind(old.as_ref().map(|v| v.0))
+    .bind(old.map(|v| v.1))
+    .bind(new.as_ref().map(|v| v.0))
+    .bind(new.map(|v| v.1))
+    .bind(version)
+    .bind(now)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// 有变化时版本号加一, 返回新的版本号
+pub async fn set(device_id: i64, items: &[SettingItem]) -> Result<i64, SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    let state = get_state_by(&mut tx, device_id).await?;
+    let version = state.version + 1;
+    let now = current_timestamp();
+    let mut changed = false;
+
+    for item in items {
+        let old = get_value(&mut tx, device_id, &item.key).await?;
+        if old == item.value {
+            continue;
+        }
+        changed = true;
+
+        match &item.value {
+            Some(value) => {
+                let (value_type, value) = value.encode();
+                sqlx::query(
+                    r#"
+                    INSERT OR REPLACE INTO tb_setting 
+                    (device_id, key, value_type, value, version, update_timestamp) 
+                    VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                )
+                .bind(device_id)
+                .bind(&item.key)
+                .bind(value_type)
+                .bind(value)
+                .bind(version)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM tb_setting WHERE device_id = ? AND key = ?")
+                    .bind(device_id)
+                    .bind(&item.key)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        };
+
+        let change = Change {
+            key: &item.key,
+            old: old.as_ref(),
+            new: item.value.as_ref(),
+        };
+        insert_history(&mut tx, device_id, &change, version, now).await?;
+    }
+
+    if !changed {
+        tx.commit().await?;
+        return Ok(state.version);
+    }
+
+    sqlx::query("UPDATE tb_setting_state SET version = ? WHERE device_id = ?")
+        .bind(version)
+        .bind(device_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(version)
+}
+
+pub async fn select(device_id: i64) -> Result<Array<TableSetting>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT key, value_type, value, version, update_timestamp 
+        FROM tb_setting WHERE device_id = ? ORDER BY key
+    "#,
+    )
+    .bind(device_id)
+    .fetch_all(get_pool())
+    .await?;
+    let vec: Vec<TableSetting> = rows.iter().filter_map(to_setting).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// since_version 之后变化过的 key
+pub async fn select_changed_keys(device_id: i64, since_version: i64) -> Result<Array<String>, SqlxErr> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT key FROM tb_setting_history WHERE device_id = ? AND version > ?",
+    )
+    .bind(device_id)
+    .bind(since_version)
+    .fetch_all(get_pool())
+    .await?;
+    let vec: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn select_history(device_id: i64) -> Result<Array<TableSettingHistory>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, key, old_value_type, old_value, new_value_type, new_value, version, create_timestamp 
+        FROM tb_setting_history WHERE device_id = ? ORDER BY id DESC
+    "#,
+    )
+    .bind(device_id)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<TableSettingHistory> = rows
+        .iter()
+        .map(|row| TableSettingHistory {
+            id: row.get(0),
+            key: row.get(1),
+            old_value: SettingValue::decode_opt(row.get(2), row.get(3)),
+            new_value: SettingValue::decode_opt(row.get(4), row.get(5)),
+            version: row.get(6),
+            create_timestamp: row.get(7),
+        })
+        .collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn set_applied(device_id: i64, applied_version: i64, reported_hash: &str) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_setting_state SET applied_version = ?, reported_hash = ? WHERE device_id = ?")
+        .bind(applied_version)
+        .bind(reported_hash)
+        .bind(device_id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+// 配置方案变化时调用, 按变化前后的生效配置写入历史, 使增量下发包含这些项
+pub async fn record_effective(
+    device_id: i64,
+    before: &[TableSetting],
+    after: &[TableSetting],
+) -> Result<(), SqlxErr> {
+    let find = |list: &'_ [TableSetting], key: &str| list.iter().find(|v| v.key == key).map(|v| v.value.clone());
+    let mut keys: Vec<&str> = before.iter().chain(after.iter()).map(|v| v.key.as_str()).collect();
+    keys.sort();
+    keys.dedup();
+    let changes: Vec<(&str, Option<SettingValue>, Option<SettingValue>)> = keys
+        .into_iter()
+        .map(|key| (key, find(before, key), find(after, key)))
+        .filter(|(_, old, new)| old != new)
+        .collect();
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = get_pool().begin().await?;
+    let state = get_state_by(&mut tx, device_id).await?;
+    let version = state.version + 1;
+    let now = current_timestamp();
+    for (key, old, new) in changes.iter() {
+        let change = Change {
+            key,
+            old: old.as_ref(),
+            new: new.as_ref(),
+        };
+        insert_history(&mut tx, device_id, &change, version, now).await?;
+    }
+    sqlx::query("UPDATE tb_setting_state SET version = ? WHERE device_id = ?")
+        .bind(version)
+        .bind(device_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn set_reported_hash(device_id: i64, reported_hash: &str) -> Result<(), SqlxErr> {
+    let mut conn = get_pool().acquire().await?;
+    get_state_by(&mut conn, device_id).await?;
+    sqlx::query("UPDATE tb_setting_state SET reported_hash = ? WHERE device_id = ?")
+        .bind(reported_hash)
+        .bind(device_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(SETTING_CREATE_SQL).await.unwrap();
+    get_pool().execute(HISTORY_CREATE_SQL).await.unwrap();
+    get_pool().execute(STATE_CREATE_SQL).await.unwrap();
+}
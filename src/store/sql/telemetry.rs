@@ -0,0 +1,191 @@
+use serde::Serialize;
+use sqlx::{Executor, Row};
+
+use crate::{error::SqlxErr, utils::Array};
+
+use super::get_pool;
+
+const RAW_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_telemetry (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        device_id INTEGER NOT NULL, 
+        metric INTEGER NOT NULL, 
+        timestamp INTEGER NOT NULL, 
+        value REAL NOT NULL
+    )
+"#;
+
+const RAW_INDEX_SQL: &str = r#"
+    CREATE INDEX IF NOT EXISTS idx_telemetry ON tb_telemetry (device_id, metric, timestamp)
+"#;
+
+const HOUR_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_telemetry_hour (
+        device_id INTEGER NOT NULL, 
+        metric INTEGER NOT NULL, 
+        bucket INTEGER NOT NULL, 
+        count INTEGER NOT NULL, 
+        sum REAL NOT NULL, 
+        min REAL NOT NULL, 
+        max REAL NOT NULL, 
+        PRIMARY KEY(device_id, metric, bucket)
+    )
+"#;
+
+const DAY_CREATE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_telemetry_day (
+        device_id INTEGER NOT NULL, 
+        metric INTEGER NOT NULL, 
+        bucket INTEGER NOT NULL, 
+        count INTEGER NOT NULL, 
+        sum REAL NOT NULL, 
+        min REAL NOT NULL, 
+        max REAL NOT NULL, 
+        PRIMARY KEY(device_id, metric, bucket)
+    )
+"#;
+
+pub mod metric {
+    pub const TEMPERATURE: i32 = 0;
+    pub const VOLTAGE: i32 = 1;
+    pub const SIGNAL: i32 = 2;
+    pub const FREE_STORAGE: i32 = 3;
+    pub const UPTIME: i32 = 4;
+}
+
+pub const HOUR: i64 = 3600;
+pub const DAY: i64 = 24 * HOUR;
+
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub timestamp: i64,
+    pub count: i64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+// values: (metric, value)
+pub async fn insert(device_id: i64, timestamp: i64, values: &[(i32, f64)]) -> Result<(), SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    for (metric, value) in values {
+        sqlx::query("INSERT INTO tb_telemetry (device_id, metric, timestamp, value) VALUES (?, ?, ?, ?)")
+            .bind(device_id)
+            .bind(metric)
+            .bind(timestamp)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+// 原始数据与各级汇总合并后按 size 秒分桶, 早于汇总精度的数据落在较粗的桶中
+pub async fn select(
+    device_id: i64,
+    metric: i32,
+    start_timestamp: i64,
+    end_timestamp: i64,
+    size: i64,
+) -> Result<Array<Bucket>, SqlxErr> {
+    let rows = sqlx::query(
+        r#"
+        SELECT b, SUM(c), SUM(s), MIN(mn), MAX(mx) FROM (
+            SELECT (timestamp / ?1) * ?1 AS b, COUNT(*) AS c, SUM(value) AS s, MIN(value) AS mn, MAX(value) AS mx 
+            FROM tb_telemetry WHERE device_id = ?2 AND metric = ?3 AND timestamp BETWEEN ?4 AND ?5 
+            GROUP BY b
+            UNION ALL 
+            SELECT (bucket / ?1) * ?1, count, sum, min, max 
+            FROM tb_telemetry_hour WHERE device_id = ?2 AND metric = ?3 AND bucket BETWEEN ?4 AND ?5 
+            UNION ALL 
+            SELECT (bucket / ?1) * ?1, count, sum, min, max 
+            FROM tb_telemetry_day WHERE device_id = ?2 AND metric = ?3 AND bucket BETWEEN ?4 AND ?5 
+        ) GROUP BY b ORDER BY b
+    "#,
+    )
+    .bind(size)
+    .bind(device_id)
+    .bind(metric)
+    .bind(start_timestamp)
+    .bind(end_timestamp)
+    .fetch_all(get_pool())
+    .await?;
+
+    let vec: Vec<Bucket> = rows
+        .iter()
+        .map(|row| Bucket {
+            timestamp: row.get(0),
+            count: row.get(1),
+            sum: row.get(2),
+            min: row.get(3),
+            max: row.get(4),
+        })
+        .collect();
+    Ok(vec.into_boxed_slice())
+}
+
+// 把 before_timestamp 之前的原始数据汇总为小时数据
+pub async fn rollup_hour(before_timestamp: i64) -> Result<(), SqlxErr> {
+    let before = before_timestamp / HOUR * HOUR;
+    let mut tx = get_pool().begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO tb_telemetry_hour (device_id, metric, bucket, count, sum, min, max) 
+        SELECT device_id, metric, (timestamp / ?1) * ?1 AS b, COUNT(*), SUM(value), MIN(value), MAX(value) 
+        FROM tb_telemetry WHERE timestamp < ?2 GROUP BY device_id, metric, b 
+        ON CONFLICT(device_id, metric, bucket) DO UPDATE SET 
+        count = tb_telemetry_hour.count + excluded.count, 
+        sum = tb_telemetry_hour.sum + excluded.sum, 
+        min = MIN(tb_telemetry_hour.min, excluded.min), 
+        max = MAX(tb_telemetry_hour.max, excluded.max)
+    "#,
+    )
+    .bind(HOUR)
+    .bind(before)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM tb_telemetry WHERE timestamp < ?")
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 把 before_timestamp 之前的小时数据汇总为天数据
+pub async fn rollup_day(before_timestamp: i64) -> Result<(), SqlxErr> {
+    let before = before_timestamp / DAY * DAY;
+    let mut tx = get_pool().begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO tb_telemetry_day (device_id, metric, bucket, count, sum, min, max) 
+        SELECT device_id, metric, (bucket / ?1) * ?1 AS b, SUM(count), SUM(sum), MIN(min), MAX(max) 
+        FROM tb_telemetry_hour WHERE bucket < ?2 GROUP BY device_id, metric, b 
+        ON CONFLICT(device_id, metric, bucket) DO UPDATE SET 
+        count = tb_telemetry_day.count + excluded.count, 
+        sum = tb_telemetry_day.sum + excluded.sum, 
+        min = MIN(tb_telemetry_day.min, excluded.min), 
+        max = MAX(tb_telemetry_day.max, excluded.max)
+    "#,
+    )
+    .bind(DAY)
+    .bind(before)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM tb_telemetry_hour WHERE bucket < ?")
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(RAW_CREATE_SQL).await.unwrap();
+    get_pool().execute(RAW_INDEX_SQL).await.unwrap();
+    get_pool().execute(HOUR_CREATE_SQL).await.unwrap();
+    get_pool().execute(DAY_CREATE_SQL).await.unwrap();
+}
@@ -0,0 +1,230 @@
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Executor, Row};
+
+use crate::{error::SqlxErr, utils::{current_timestamp, Array}};
+
+use super::{add_column, get_pool};
+
+const CREATE_USER_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_user (
+        id INTEGER PRIMARY KEY AUTOINCREMENT, 
+        username TEXT NOT NULL, 
+        password_hash TEXT NOT NULL, 
+        salt TEXT NOT NULL, 
+        role INTEGER NOT NULL DEFAULT 0, 
+        create_timestamp INTEGER NOT NULL, 
+        update_timestamp INTEGER NOT NULL, 
+        UNIQUE(username)
+    )
+"#;
+
+// token 只保存哈希值
+const CREATE_SESSION_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS tb_session (
+        token_hash TEXT PRIMARY KEY, 
+        user_id INTEGER NOT NULL, 
+        create_timestamp INTEGER NOT NULL, 
+        expire_timestamp INTEGER NOT NULL
+    )
+"#;
+
+pub mod role {
+    pub const VIEWER: i32 = 0;
+    pub const OPERATOR: i32 = 1;
+    pub const FINANCE: i32 = 2;
+    pub const ADMIN: i32 = 3;
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableUser {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    #[serde(skip)]
+    pub salt: String,
+    pub role: i32,
+    pub create_timestamp: i64,
+    pub update_timestamp: i64,
+}
+
+#[derive(Debug)]
+pub struct TableSession {
+    pub user_id: i64,
+    pub username: String,
+    pub role: i32,
+}
+
+const SELECT_SQL: &str = r#"
+    SELECT id, username, password_hash, salt, role, create_timestamp, update_timestamp FROM tb_user
+"#;
+
+fn to_user(row: &SqliteRow) -> TableUser {
+    TableUser {
+        id: row.get(0),
+        username: row.get(1),
+        password_hash: row.get(2),
+        salt: row.get(3),
+        role: row.get(4),
+        create_timestamp: row.get(5),
+        update_timestamp: row.get(6),
+    }
+}
+
+pub async fn create(username: &str, password_hash: &str, salt: &str, role: i32) -> Result<i64, SqlxErr> {
+    let now = current_timestamp();
+    let ret = sqlx::query(
+        r#"
+        INSERT INTO tb_user (username, password_hash, salt, role, create_timestamp, update_timestamp) 
+        VALUES (?, ?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(username)
+    .bind(password_hash)
+    .bind(salt)
+    .bind(role)
+    .bind(now)
+    .bind(now)
+    .execute(get_pool())
+    .await?;
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn count() -> Result<i64, SqlxErr> {
+    let row = sqlx::query("SELECT COUNT(*) FROM tb_user")
+        .fetch_one(get_pool())
+        .await?;
+    Ok(row.get(0))
+}
+
+pub async fn get(id: i64) -> Result<TableUser, SqlxErr> {
+    let sql = format!("{} WHERE id = ?", SELECT_SQL);
+    let row = sqlx::query(&sql).bind(id).fetch_one(get_pool()).await?;
+    Ok(to_user(&row))
+}
+
+pub async fn get_by_name(username: &str) -> Result<Option<TableUser>, SqlxErr> {
+    let sql = format!("{} WHERE username = ?", SELECT_SQL);
+    let row = sqlx::query(&sql)
+        .bind(username)
+        .fetch_optional(get_pool())
+        .await?;
+    Ok(row.as_ref().map(to_user))
+}
+
+pub async fn select() -> Result<Array<TableUser>, SqlxErr> {
+    let rows = sqlx::query(SELECT_SQL).fetch_all(get_pool()).await?;
+    let vec: Vec<TableUser> = rows.iter().map(to_user).collect();
+    Ok(vec.into_boxed_slice())
+}
+
+pub async fn set_password(id: i64, password_hash: &str, salt: &str) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_user SET password_hash = ?, salt = ?, update_timestamp = ? WHERE id = ?")
+        .bind(password_hash)
+        .bind(salt)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn set_role(id: i64, role: i32) -> Result<(), SqlxErr> {
+    sqlx::query("UPDATE tb_user SET role = ?, update_timestamp = ? WHERE id = ?")
+        .bind(role)
+        .bind(current_timestamp())
+        .bind(id)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn delete(id: i64) -> Result<(), SqlxErr> {
+    let mut tx = get_pool().begin().await?;
+    sqlx::query("DELETE FROM tb_session WHERE user_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM tb_user WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn create_session(token_hash: &str, user_id: i64, expire_timestamp: i64) -> Result<(), SqlxErr> {
+    sqlx::query(
+        r#"
+        INSERT INTO tb_session (token_hash, user_id, create_timestamp, expire_timestamp) 
+        VALUES (?, ?, ?, ?)
+    "#,
+    )
+    .bind(token_hash)
+    .bind(user_id)
+    .bind(current_timestamp())
+    .bind(expire_timestamp)
+    .execute(get_pool())
+    .await?;
+    Ok(())
+}
+
+// 只返回未过期的会话
+pub async fn get_session(token_hash: &str) -> Result<Option<TableSession>, SqlxErr> {
+    let row = sqlx::query(
+        r#"
+        SELECT s.user_id, u.username, u.role 
+        FROM tb_session s JOIN tb_user u ON u.id = s.user_id 
+        WHERE s.token_hash = ? AND s.expire_timestamp > ?
+    "#,
+    )
+    .bind(token_hash)
+    .bind(current_timestamp())
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|row| TableSession {
+        user_id: row.get(0),
+        username: row.get(1),
+        role: row.get(2),
+    }))
+}
+
+pub async fn delete_session(token_hash: &str) -> Result<(), SqlxErr> {
+    sqlx::query("DELETE FROM tb_session WHERE token_hash = ?")
+        .bind(token_hash)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+// 修改密码后使其他会话失效
+pub async fn delete_other_sessions(user_id: i64, token_hash: &str) -> Result<(), SqlxErr> {
+    sqlx::query("DELETE FROM tb_session WHERE user_id = ? AND token_hash != ?")
+        .bind(user_id)
+        .bind(token_hash)
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_expired_sessions() -> Result<(), SqlxErr> {
+    sqlx::query("DELETE FROM tb_session WHERE expire_timestamp <= ?")
+        .bind(current_timestamp())
+        .execute(get_pool())
+        .await?;
+    Ok(())
+}
+
+pub async fn init() {
+    get_pool().execute(CREATE_USER_SQL).await.unwrap();
+    get_pool().execute(CREATE_SESSION_SQL).await.unwrap();
+    // 区分角色之前的账号都拥有全部权限, 升级时显式设为管理员
+    if add_column("tb_user", "role", "INTEGER NOT NULL DEFAULT 0").await {
+        sqlx::query("UPDATE tb_user SET role = ?")
+            .bind(role::ADMIN)
+            .execute(get_pool())
+            .await
+            .unwrap();
+    }
+}
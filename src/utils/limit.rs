@@ -0,0 +1,78 @@
+use dashmap::DashMap;
+
+struct FailWindow {
+    count: u32,
+    start_timestamp: i64,
+}
+
+// 窗口期内失败次数达到上限后拒绝, 缓存满时先清理过期项, 仍满则淘汰最早的窗口
+pub struct FailLimiter {
+    windows: DashMap<String, FailWindow>,
+    max: u32,
+    window_secs: i64,
+    capacity: usize,
+}
+
+impl FailLimiter {
+    pub fn new(max: u32, window_secs: i64, capacity: usize) -> Self {
+        Self {
+            windows: DashMap::new(),
+            max,
+            window_secs,
+            capacity,
+        }
+    }
+
+    fn is_expired(&self, w: &FailWindow, now: i64) -> bool {
+        now - w.start_timestamp >= self.window_secs
+    }
+
+    pub fn is_limited(&self, key: &str, now: i64) -> bool {
+        let limited = match self.windows.get(key) {
+            Some(w) if self.is_expired(&w, now) => None,
+            Some(w) => Some(w.count >= self.max),
+            None => Some(false),
+        };
+        match limited {
+            Some(limited) => limited,
+            None => {
+                self.windows.remove_if(key, |_, w| self.is_expired(w, now));
+                false
+            }
+        }
+    }
+
+    fn evict(&self, now: i64) {
+        self.windows.retain(|_, w| !self.is_expired(w, now));
+        if self.windows.len() < self.capacity {
+            return;
+        }
+        let oldest = self
+            .windows
+            .iter()
+            .min_by_key(|w| w.start_timestamp)
+            .map(|w| w.key().clone());
+        if let Some(key) = oldest {
+            self.windows.remove(&key);
+        }
+    }
+
+    pub fn add(&self, key: String, now: i64) {
+        if !self.windows.contains_key(&key) && self.windows.len() >= self.capacity {
+            self.evict(now);
+        }
+        let mut w = self.windows.entry(key).or_insert(FailWindow {
+            count: 0,
+            start_timestamp: now,
+        });
+        if self.is_expired(&w, now) {
+            w.count = 0;
+            w.start_timestamp = now;
+        }
+        w.count += 1;
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.windows.remove(key);
+    }
+}
@@ -1,8 +1,10 @@
 use std::{ptr::NonNull, time::SystemTime};
 
 use rand::Rng;
+use sha2::{Digest, Sha256};
 
 pub mod codec;
+pub mod limit;
 
 pub type Array<T> = Box<[T]>;
 
@@ -21,6 +23,13 @@ pub fn current_timestamp() -> i64 {
         .as_secs() as i64
 }
 
+pub fn current_timestamp_millis() -> i64 {
+    let now = SystemTime::now();
+    now.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
 pub fn get_mut<T>(value: &T) -> &mut T {
     unsafe {
         NonNull::new_unchecked(value as *const T as *mut T).as_mut()
@@ -31,3 +40,15 @@ pub fn rand_u8() -> u8 {
     let mut rng = rand::thread_rng();
     rng.gen_range(0..=255)
 }
+
+pub fn to_hex(buf: &[u8]) -> String {
+    let mut s = String::with_capacity(buf.len() * 2);
+    for v in buf {
+        s.push_str(&format!("{:02x}", v));
+    }
+    s
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
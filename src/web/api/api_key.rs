@@ -0,0 +1,88 @@
+use crate::error::validation;
+use crate::store;
+use crate::store::api_key::TableApiKey;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::auth::new_api_key;
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+#[derive(Debug, Deserialize)]
+struct CreateReq {
+    name: String,
+    // 权限位, 与创建者权限取交集后生效
+    scopes: u32,
+    expire_timestamp: Option<i64>,
+    ip_allowlist: Option<Array<String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateRes {
+    id: i64,
+    // 明文密钥只在创建时返回一次
+    key: String,
+}
+
+#[post("/create")]
+async fn create(actor: Actor, req: Cbor<CreateReq>) -> CborRes<CreateRes> {
+    if req.name.trim().is_empty() {
+        return validation("名称不能为空");
+    }
+    if req.scopes == 0 {
+        return validation("权限范围不能为空");
+    }
+    let ip_allowlist = match &req.ip_allowlist {
+        // 统一保存为规范形式, IPv4 映射的 IPv6 地址按 IPv4 保存
+        Some(ips) => {
+            let parsed: Result<Vec<IpAddr>, _> = ips.iter().map(|ip| ip.trim().parse::<IpAddr>()).collect();
+            match parsed {
+                Ok(parsed) if !parsed.is_empty() => {
+                    let ips: Vec<String> = parsed.iter().map(|ip| ip.to_canonical().to_string()).collect();
+                    Some(ips.join(","))
+                }
+                _ => return validation("无效的IP地址"),
+            }
+        }
+        None => None,
+    };
+    let (key, key_hash, key_prefix) = new_api_key();
+    let id = store::api_key::create(
+        &req.name,
+        &key_hash,
+        &key_prefix,
+        req.scopes,
+        req.expire_timestamp,
+        ip_allowlist.as_deref(),
+        actor.session.user_id,
+    )
+    .await?;
+    let after = store::api_key::get(id).await?;
+    actor.log("api_key.create", None, None, json(&after)).await;
+    new_cbor(CreateRes { id, key })
+}
+
+#[post("/select")]
+async fn select() -> CborRes<Array<TableApiKey>> {
+    let keys = store::api_key::select().await?;
+    new_cbor(keys)
+}
+
+#[post("/revoke")]
+async fn revoke(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let before = store::api_key::get(*id).await?;
+    store::api_key::revoke(*id).await?;
+    let after = store::api_key::get(*id).await?;
+    actor.log("api_key.revoke", None, json(&before), json(&after)).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/api_key")
+        .service(create)
+        .service(select)
+        .service(revoke);
+    cfg.service(scope);
+}
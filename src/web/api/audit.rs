@@ -0,0 +1,51 @@
+use crate::error::validation;
+use crate::store;
+use crate::store::audit::{AuditFilter, AuditPage};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::Deserialize;
+
+const PAGE_MAX_SIZE: i64 = 500;
+const PAGE_DEFAULT_SIZE: i64 = 50;
+
+fn default_size() -> i64 {
+    PAGE_DEFAULT_SIZE
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectReq {
+    actor: Option<String>,
+    // 前缀匹配, 如 device. 或 coin.set_mask
+    action: Option<String>,
+    device_id: Option<i64>,
+    start_timestamp: Option<i64>,
+    end_timestamp: Option<i64>,
+    // 从 0 开始
+    #[serde(default)]
+    page: i64,
+    #[serde(default = "default_size")]
+    size: i64,
+}
+
+#[post("/select")]
+async fn select(req: Cbor<SelectReq>) -> CborRes<AuditPage> {
+    let offset = match req.page.checked_mul(req.size) {
+        Some(offset) if req.page >= 0 && req.size > 0 && req.size <= PAGE_MAX_SIZE => offset,
+        _ => return validation("无效的分页参数"),
+    };
+    let filter = AuditFilter {
+        actor: req.actor.as_deref(),
+        action: req.action.as_deref(),
+        device_id: req.device_id,
+        start_timestamp: req.start_timestamp,
+        end_timestamp: req.end_timestamp,
+    };
+    let page = store::audit::select(&filter, offset, req.size).await?;
+    new_cbor(page)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/audit").service(select);
+    cfg.service(scope);
+}
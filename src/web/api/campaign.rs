@@ -0,0 +1,120 @@
+use crate::error::{conflict, validation};
+use crate::serve::api::campaign::Targets;
+use crate::store::campaign::{state, TableCampaign, WaveStats};
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreateReq {
+    name: String,
+    firmware_id: i64,
+    device_ids: Option<Array<i64>>,
+    from_version: Option<String>,
+    waves: Array<u8>,
+    failure_threshold: u8,
+}
+
+#[post("/create")]
+async fn create(actor: Actor, req: Cbor<CreateReq>) -> CborRes<i64> {
+    let targets = match (&req.device_ids, &req.from_version) {
+        (Some(ids), None) => Targets::Devices(ids),
+        (None, Some(version)) => Targets::Version(version),
+        _ => return validation("device_ids和from_version必须二选一"),
+    };
+    let id = serve::api::campaign::create(
+        &req.name,
+        req.firmware_id,
+        targets,
+        &req.waves,
+        req.failure_threshold,
+    )
+    .await?;
+    actor.log("campaign.create", None, None, json(&*req)).await;
+    new_cbor(id)
+}
+
+#[post("/select")]
+async fn select() -> CborRes<Array<TableCampaign>> {
+    let campaigns = store::campaign::select().await?;
+    new_cbor(campaigns)
+}
+
+#[derive(Debug, Serialize)]
+struct Progress {
+    campaign: TableCampaign,
+    waves: Array<WaveStats>,
+}
+
+#[post("/progress")]
+async fn progress(id: Cbor<i64>) -> CborRes<Progress> {
+    let campaign = store::campaign::get(*id).await?;
+    let waves = store::campaign::wave_stats(*id).await?;
+    new_cbor(Progress { campaign, waves })
+}
+
+#[post("/pause")]
+async fn pause(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let campaign = store::campaign::get(*id).await?;
+    if campaign.state != state::RUNNING {
+        return conflict("发布计划未在运行");
+    }
+    if !store::campaign::set_state(*id, state::RUNNING, state::PAUSED, None).await? {
+        return conflict("发布计划未在运行");
+    }
+    let after = store::campaign::get(*id).await?;
+    actor.log("campaign.pause", None, json(&campaign), json(&after)).await;
+    new_cbor(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResumeReq {
+    id: i64,
+    failure_threshold: Option<u8>,
+}
+
+#[post("/resume")]
+async fn resume(actor: Actor, req: Cbor<ResumeReq>) -> CborRes<()> {
+    let campaign = store::campaign::get(req.id).await?;
+    if campaign.state != state::PAUSED {
+        return conflict("发布计划未暂停");
+    }
+    if let Some(threshold) = req.failure_threshold {
+        serve::api::campaign::check_threshold(threshold)?;
+    }
+    if !store::campaign::resume(req.id, req.failure_threshold).await? {
+        return conflict("发布计划未暂停");
+    }
+    let after = store::campaign::get(req.id).await?;
+    actor.log("campaign.resume", None, json(&campaign), json(&after)).await;
+    new_cbor(())
+}
+
+#[post("/cancel")]
+async fn cancel(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let campaign = store::campaign::get(*id).await?;
+    if campaign.state == state::FINISHED || campaign.state == state::CANCELLED {
+        return conflict("发布计划已结束");
+    }
+    if !store::campaign::cancel(*id).await? {
+        return conflict("发布计划已结束");
+    }
+    let after = store::campaign::get(*id).await?;
+    actor.log("campaign.cancel", None, json(&campaign), json(&after)).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/campaign")
+        .service(create)
+        .service(select)
+        .service(progress)
+        .service(pause)
+        .service(resume)
+        .service(cancel);
+    cfg.service(scope);
+}
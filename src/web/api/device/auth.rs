@@ -0,0 +1,57 @@
+use crate::serve;
+use crate::store;
+use crate::store::auth::TableAuthFail;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+// 返回十六进制密钥, 只在此处可见
+#[post("/issue")]
+async fn issue(actor: Actor, device_id: Cbor<i64>) -> CborRes<String> {
+    let secret = serve::api::auth::issue(*device_id).await?;
+    actor.log("auth.issue", Some(*device_id), None, None).await;
+    new_cbor(secret)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RotateReq {
+    device_id: i64,
+    reason: String,
+}
+
+#[post("/rotate")]
+async fn rotate(actor: Actor, req: Cbor<RotateReq>) -> CborRes<()> {
+    let ret = serve::api::auth::rotate(req.device_id, &actor.op(&req.reason)).await;
+    actor.log_ret("auth.rotate", Some(req.device_id), json(&*req), &ret).await;
+    ret?;
+    new_cbor(())
+}
+
+#[derive(Debug, Deserialize)]
+struct FailsReq {
+    mac_addr: Option<String>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+#[post("/fails")]
+async fn fails(req: Cbor<FailsReq>) -> CborRes<Array<TableAuthFail>> {
+    let fails = store::auth::select_fail(
+        req.mac_addr.as_deref(),
+        req.start_timestamp,
+        req.end_timestamp,
+    )
+    .await?;
+    new_cbor(fails)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/auth")
+        .service(issue)
+        .service(rotate)
+        .service(fails);
+    cfg.service(scope);
+}
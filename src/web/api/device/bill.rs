@@ -1,4 +1,6 @@
-use crate::store;
+use crate::error::ErrorExt;
+use crate::{serve, store};
+use crate::web::audit::{json, Actor};
 use crate::web::resp::{new_cbor, Cbor, CborRes};
 use ntex::web::post;
 use ntex::web::{self, ServiceConfig};
@@ -18,8 +20,15 @@ struct TypeMaskReq {
 }
 
 #[post("/set_mask")]
-async fn set_mask(req: Cbor<TypeMaskReq>) -> CborRes<()> {
+async fn set_mask(actor: Actor, req: Cbor<TypeMaskReq>) -> CborRes<()> {
+    let before = store::bill::get(req.device_id).await?;
     store::bill::set_type_mask(req.device_id, req.mask).await?;
+    store::profile::set_bill_override(req.device_id, Some(req.mask)).await?;
+    serve::api::bill::push_mask(req.device_id)
+        .await
+        .print_if_err();
+    let after = store::bill::get(req.device_id).await?;
+    actor.log("bill.set_mask", Some(req.device_id), json(&before), json(&after)).await;
     new_cbor(())
 }
 
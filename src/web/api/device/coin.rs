@@ -1,8 +1,14 @@
-use crate::store;
+use crate::error::ErrorExt;
+use crate::serve::api::coin::PayoutRes;
+use crate::store::coin::TableCoinInfoHistory;
+use crate::store::payout::{PayoutCount, TablePayout};
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
 use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
 use ntex::web::post;
 use ntex::web::{self, ServiceConfig};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[post("/get")]
 async fn get(device_id: Cbor<i64>) -> CborRes<store::coin::TableCoin> {
@@ -17,6 +23,21 @@ async fn get_info(device_id: Cbor<i64>) -> CborRes<store::coin::TableCoinInfos>
     new_cbor(infos)
 }
 
+#[derive(Debug, Deserialize)]
+struct InfoHistoryReq {
+    device_id: i64,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+#[post("/info_history")]
+async fn info_history(req: Cbor<InfoHistoryReq>) -> CborRes<Array<TableCoinInfoHistory>> {
+    let history =
+        store::coin::select_info_history(req.device_id, req.start_timestamp, req.end_timestamp)
+            .await?;
+    new_cbor(history)
+}
+
 #[derive(Debug, Deserialize)]
 struct TypeMaskReq {
     device_id: i64,
@@ -24,15 +45,52 @@ struct TypeMaskReq {
 }
 
 #[post("/set_mask")]
-async fn set_mask(req: Cbor<TypeMaskReq>) -> CborRes<()> {
+async fn set_mask(actor: Actor, req: Cbor<TypeMaskReq>) -> CborRes<()> {
+    let before = store::coin::get(req.device_id).await?;
     store::coin::set_type_mask(req.device_id, req.mask).await?;
+    store::profile::set_coin_override(req.device_id, Some(req.mask)).await?;
+    serve::api::coin::push_mask(req.device_id)
+        .await
+        .print_if_err();
+    let after = store::coin::get(req.device_id).await?;
+    actor.log("coin.set_mask", Some(req.device_id), json(&before), json(&after)).await;
     new_cbor(())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct PayoutReq {
+    device_id: i64,
+    request_key: String,
+    amount: Option<u32>,
+    counts: Option<Array<PayoutCount>>,
+}
+
+#[post("/payout")]
+async fn payout(actor: Actor, req: Cbor<PayoutReq>) -> CborRes<PayoutRes> {
+    let res = serve::api::coin::payout(
+        req.device_id,
+        &req.request_key,
+        req.amount,
+        req.counts.as_deref(),
+    )
+    .await;
+    actor.log_ret("coin.payout", Some(req.device_id), json(&*req), &res).await;
+    new_cbor(res?)
+}
+
+#[post("/payout_logs")]
+async fn payout_logs(device_id: Cbor<i64>) -> CborRes<Array<TablePayout>> {
+    let logs = store::payout::select(*device_id).await?;
+    new_cbor(logs)
+}
+
 pub fn register(cfg: &mut ServiceConfig) {
     let scope = web::scope("/coin")
         .service(get)
         .service(get_info)
-        .service(set_mask);
+        .service(info_history)
+        .service(set_mask)
+        .service(payout)
+        .service(payout_logs);
     cfg.service(scope);
 }
@@ -0,0 +1,72 @@
+use crate::serve;
+use crate::store;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RebootReq {
+    device_id: i64,
+    target: u8,
+    reason: String,
+}
+
+#[post("/reboot")]
+async fn reboot(actor: Actor, req: Cbor<RebootReq>) -> CborRes<()> {
+    let ret = serve::api::device::reboot(req.device_id, req.target, &actor.op(&req.reason)).await;
+    actor.log_ret("command.reboot", Some(req.device_id), json(&*req), &ret).await;
+    ret?;
+    new_cbor(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InhibitReq {
+    device_id: i64,
+    peripheral: u8,
+    // 自动恢复时间, 为空时需手动恢复
+    until: Option<i64>,
+    reason: String,
+}
+
+#[post("/inhibit")]
+async fn inhibit(actor: Actor, req: Cbor<InhibitReq>) -> CborRes<()> {
+    let op = actor.op(&req.reason);
+    let ret = serve::api::inhibit::set(req.device_id, req.peripheral, true, req.until, &op).await;
+    actor.log_ret("command.inhibit", Some(req.device_id), json(&*req), &ret).await;
+    ret?;
+    new_cbor(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EnableReq {
+    device_id: i64,
+    peripheral: u8,
+    reason: String,
+}
+
+#[post("/enable")]
+async fn enable(actor: Actor, req: Cbor<EnableReq>) -> CborRes<()> {
+    let op = actor.op(&req.reason);
+    let ret = serve::api::inhibit::set(req.device_id, req.peripheral, false, None, &op).await;
+    actor.log_ret("command.enable", Some(req.device_id), json(&*req), &ret).await;
+    ret?;
+    new_cbor(())
+}
+
+#[post("/logs")]
+async fn logs(device_id: Cbor<i64>) -> CborRes<Array<store::command::TableCommandLog>> {
+    let logs = store::command::select(*device_id).await?;
+    new_cbor(logs)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/command")
+        .service(reboot)
+        .service(inhibit)
+        .service(enable)
+        .service(logs);
+    cfg.service(scope);
+}
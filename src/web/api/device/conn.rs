@@ -0,0 +1,34 @@
+use crate::serve::{self, api::ConnState};
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct SelectReq {
+    device_id: Option<i64>,
+    addr: Option<String>,
+}
+
+#[post("/select")]
+async fn select(req: Cbor<SelectReq>) -> CborRes<Vec<ConnState>> {
+    let states = serve::api::device::connections(req.device_id, req.addr.as_deref());
+    new_cbor(states)
+}
+
+#[post("/kick")]
+async fn kick(actor: Actor, session_id: Cbor<u64>) -> CborRes<()> {
+    let before = serve::api::device::connections(None, None)
+        .into_iter()
+        .find(|v| v.session_id == *session_id);
+    serve::api::device::kick(*session_id)?;
+    let device_id = before.as_ref().map(|v| v.device_id);
+    actor.log("conn.kick", device_id, json(&before), None).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/conn").service(select).service(kick);
+    cfg.service(scope);
+}
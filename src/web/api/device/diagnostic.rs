@@ -0,0 +1,45 @@
+use crate::serve;
+use crate::store;
+use crate::store::diagnostic::TableDiagnostic;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RunReq {
+    device_id: i64,
+    items: u8,
+    reason: String,
+}
+
+#[post("/run")]
+async fn run(actor: Actor, req: Cbor<RunReq>) -> CborRes<TableDiagnostic> {
+    let ret = serve::api::device::self_test(req.device_id, req.items, &actor.op(&req.reason)).await;
+    actor.log_ret("diagnostic.run", Some(req.device_id), json(&*req), &ret).await;
+    let id = ret?;
+    let diagnostic = store::diagnostic::get(id).await?;
+    new_cbor(diagnostic)
+}
+
+#[post("/select")]
+async fn select(device_id: Cbor<i64>) -> CborRes<Array<TableDiagnostic>> {
+    let diagnostics = store::diagnostic::select(*device_id).await?;
+    new_cbor(diagnostics)
+}
+
+#[post("/get")]
+async fn get(id: Cbor<i64>) -> CborRes<TableDiagnostic> {
+    let diagnostic = store::diagnostic::get(*id).await?;
+    new_cbor(diagnostic)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/diagnostic")
+        .service(run)
+        .service(select)
+        .service(get);
+    cfg.service(scope);
+}
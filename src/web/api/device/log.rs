@@ -0,0 +1,54 @@
+use crate::store::device_log::TableDeviceLog;
+use crate::store::file;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadReq {
+    device_id: i64,
+    since_timestamp: i64,
+}
+
+#[post("/upload")]
+async fn upload(actor: Actor, req: Cbor<UploadReq>) -> CborRes<()> {
+    let ret = serve::api::device_log::request_upload(req.device_id, req.since_timestamp).await;
+    actor.log_ret("log.upload", Some(req.device_id), json(&*req), &ret).await;
+    ret?;
+    new_cbor(())
+}
+
+#[post("/select")]
+async fn select(device_id: Cbor<i64>) -> CborRes<Array<TableDeviceLog>> {
+    let logs = store::device_log::select(*device_id).await?;
+    new_cbor(logs)
+}
+
+#[post("/download")]
+async fn download(id: Cbor<i64>) -> CborRes<ByteBuf> {
+    let log = store::device_log::get(*id).await?;
+    let data = file::read(&file::log_path(log.device_id, log.id)).await?;
+    new_cbor(ByteBuf::from(data))
+}
+
+#[post("/delete")]
+async fn delete(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let before = store::device_log::get(*id).await?;
+    serve::api::device_log::remove(*id).await?;
+    actor.log("log.delete", Some(before.device_id), json(&before), None).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/log")
+        .service(upload)
+        .service(select)
+        .service(download)
+        .service(delete);
+    cfg.service(scope);
+}
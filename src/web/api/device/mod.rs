@@ -1,15 +1,28 @@
 use crate::{
+    error::{conflict, validation},
+    serve,
     store,
-    utils::Array,
-    web::resp::{new_cbor, Cbor, CborRes},
+    web::{
+        audit::{json, Actor},
+        resp::{new_cbor, Cbor, CborRes},
+    },
 };
 use ntex::web::{self, post, ServiceConfig};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use std::time::Duration;
 
+mod auth;
 mod bill;
 mod coin;
+mod command;
+mod conn;
+mod diagnostic;
+mod log;
+mod setting;
+mod telemetry;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct CreateReq {
     name: String,
     address: String,
@@ -17,9 +30,13 @@ struct CreateReq {
 }
 
 #[post("/create")]
-async fn create(req: Cbor<CreateReq>) -> CborRes<i64> {
+async fn create(actor: Actor, req: Cbor<CreateReq>) -> CborRes<i64> {
     use store::device::*;
+    if get_id_by_mac(&req.mac_addr).await?.is_some() {
+        return conflict("MAC地址已存在");
+    }
     let id = create_by(&req.mac_addr, &req.name, &req.address).await?;
+    actor.log("device.create", Some(id), None, json(&*req)).await;
     new_cbor(id)
 }
 
@@ -30,17 +47,63 @@ async fn get_by_id(id: Cbor<i64>) -> CborRes<store::device::TableDevice> {
     new_cbor(info)
 }
 
+const PAGE_MAX_SIZE: i64 = 500;
+const PAGE_DEFAULT_SIZE: i64 = 50;
+
+fn default_size() -> i64 {
+    PAGE_DEFAULT_SIZE
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectReq {
+    name: Option<String>,
+    address: Option<String>,
+    mac_addr: Option<String>,
+    app_version: Option<String>,
+    mcu_version: Option<String>,
+    online: Option<bool>,
+    create_start: Option<i64>,
+    create_end: Option<i64>,
+    sort: Option<u8>,
+    #[serde(default)]
+    desc: bool,
+    // 从 0 开始
+    #[serde(default)]
+    page: i64,
+    #[serde(default = "default_size")]
+    size: i64,
+}
+
 #[post("/select")]
-async fn select() -> CborRes<Array<store::device::TableDevice>> {
+async fn select(req: Cbor<SelectReq>) -> CborRes<store::device::DevicePage> {
     use store::device::*;
-    let infos = select().await?;
-    new_cbor(infos)
+    let offset = match req.page.checked_mul(req.size) {
+        Some(offset) if req.page >= 0 && req.size > 0 && req.size <= PAGE_MAX_SIZE => offset,
+        _ => return validation("无效的分页参数"),
+    };
+    let online_ids = req.online.map(|_| serve::api::device::online_ids());
+    let filter = DeviceFilter {
+        name: req.name.as_deref(),
+        address: req.address.as_deref(),
+        mac_addr: req.mac_addr.as_deref(),
+        app_version: req.app_version.as_deref(),
+        mcu_version: req.mcu_version.as_deref(),
+        create_start: req.create_start,
+        create_end: req.create_end,
+        include_ids: online_ids.as_deref().filter(|_| req.online == Some(true)),
+        exclude_ids: online_ids.as_deref().filter(|_| req.online == Some(false)),
+    };
+    let sort = req.sort.unwrap_or(sort::ID);
+    let page = select(&filter, sort, req.desc, offset, req.size).await?;
+    new_cbor(page)
 }
 
 #[post("/delete")]
-async fn delete(id: Cbor<i64>) -> CborRes<()> {
+async fn delete(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
     use store::device::*;
+    let before = get(*id).await?;
     delete(*id).await?;
+    actor.log("device.delete", Some(*id), json(&before), None).await;
     new_cbor(())
 }
 
@@ -53,23 +116,54 @@ struct UpdateReq {
 }
 
 #[post("/update")]
-async fn update(req: Cbor<UpdateReq>) -> CborRes<()> {
+async fn update(actor: Actor, req: Cbor<UpdateReq>) -> CborRes<()> {
     use store::device::*;
 
+    let before = get(req.id).await?;
     set_mac_addr(req.id, req.mac_addr.as_deref()).await?;
     set_name(req.id, req.name.as_deref()).await?;
     set_address(req.id, req.address.as_deref()).await?;
+    let after = get(req.id).await?;
+    actor.log("device.update", Some(req.id), json(&before), json(&after)).await;
 
     new_cbor(())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcReq {
+    device_id: i64,
+    cmd: u8,
+    body: Value,
+    timeout_ms: u64,
+    reason: String,
+}
+
+// 设备返回的错误原样放入错误响应
+#[post("/rpc")]
+async fn rpc(actor: Actor, req: Cbor<RpcReq>) -> CborRes<Value> {
+    let timeout = Duration::from_millis(req.timeout_ms);
+    let op = actor.op(&req.reason);
+    let ret = serve::api::device::rpc(req.device_id, req.cmd, &req.body, timeout, &op).await;
+    actor.log_ret("device.rpc", Some(req.device_id), json(&*req), &ret).await;
+    new_cbor(ret?)
+}
+
 pub fn register(cfg: &mut ServiceConfig) {
     let scope = web::scope("/device")
         .service(create)
         .service(get_by_id)
         .service(select)
+        .service(delete)
         .service(update)
+        .service(rpc)
+        .configure(auth::register)
         .configure(coin::register)
-        .configure(bill::register);
+        .configure(bill::register)
+        .configure(command::register)
+        .configure(conn::register)
+        .configure(diagnostic::register)
+        .configure(log::register)
+        .configure(setting::register)
+        .configure(telemetry::register);
     cfg.service(scope);
 }
@@ -0,0 +1,55 @@
+use crate::error::ErrorExt;
+use crate::store::setting::{SettingItem, TableSetting, TableSettingHistory, TableSettingState};
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+struct SettingInfo {
+    state: TableSettingState,
+    hash: String,
+    items: Array<TableSetting>,
+}
+
+#[post("/get")]
+async fn get(device_id: Cbor<i64>) -> CborRes<SettingInfo> {
+    let state = store::setting::get_state(*device_id).await?;
+    let items = serve::api::setting::expected(*device_id).await?;
+    let hash = store::setting::hash(&items);
+    new_cbor(SettingInfo { state, hash, items })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SetReq {
+    device_id: i64,
+    items: Array<SettingItem>,
+}
+
+#[post("/set")]
+async fn set(actor: Actor, req: Cbor<SetReq>) -> CborRes<i64> {
+    let before = serve::api::setting::expected(req.device_id).await?;
+    let version = store::setting::set(req.device_id, &req.items).await?;
+    actor.log("setting.set", Some(req.device_id), json(&before), json(&*req)).await;
+    serve::api::setting::push_changes(req.device_id)
+        .await
+        .print_if_err();
+    new_cbor(version)
+}
+
+#[post("/history")]
+async fn history(device_id: Cbor<i64>) -> CborRes<Array<TableSettingHistory>> {
+    let history = store::setting::select_history(*device_id).await?;
+    new_cbor(history)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/setting")
+        .service(get)
+        .service(set)
+        .service(history);
+    cfg.service(scope);
+}
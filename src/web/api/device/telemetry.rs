@@ -0,0 +1,69 @@
+use crate::error::validation;
+use crate::store;
+use crate::utils::Array;
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+mod agg {
+    pub const AVG: u8 = 0;
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = 2;
+    pub const SUM: u8 = 3;
+    pub const COUNT: u8 = 4;
+}
+
+#[derive(Debug, Deserialize)]
+struct SeriesReq {
+    device_id: i64,
+    metric: i32,
+    start_timestamp: i64,
+    end_timestamp: i64,
+    // 分桶间隔, 秒
+    interval: i64,
+    agg: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct Point {
+    timestamp: i64,
+    value: f64,
+}
+
+#[post("/series")]
+async fn series(req: Cbor<SeriesReq>) -> CborRes<Array<Point>> {
+    if req.interval < 1 {
+        return validation("无效的时间间隔");
+    }
+    let buckets = store::telemetry::select(
+        req.device_id,
+        req.metric,
+        req.start_timestamp,
+        req.end_timestamp,
+        req.interval,
+    )
+    .await?;
+
+    let mut points = Vec::with_capacity(buckets.len());
+    for b in buckets.iter() {
+        let value = match req.agg {
+            agg::AVG => b.sum / b.count as f64,
+            agg::MIN => b.min,
+            agg::MAX => b.max,
+            agg::SUM => b.sum,
+            agg::COUNT => b.count as f64,
+            _ => return validation("无效的聚合函数"),
+        };
+        points.push(Point {
+            timestamp: b.timestamp,
+            value,
+        });
+    }
+    new_cbor(points.into_boxed_slice())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/telemetry").service(series);
+    cfg.service(scope);
+}
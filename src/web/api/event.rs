@@ -0,0 +1,154 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::config::{EVENT_CLIENT_BUFFER, EVENT_KEEPALIVE_SECS, EVENT_SEND_TIMEOUT_SECS};
+use crate::error::{validation, AppErr};
+use crate::serve::api::event::{self, Event, SharedEvent};
+use ntex::util::{Bytes, Stream};
+use ntex::web::types::Query;
+use ntex::web::{self, get, HttpRequest, HttpResponse, ServiceConfig};
+use serde::Deserialize;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::mpsc;
+use tokio::time;
+
+const HEAD_LAST_EVENT_ID: &str = "last-event-id";
+const KEEPALIVE: &[u8] = b": keepalive\n\n";
+
+#[derive(Debug, Deserialize)]
+struct StreamReq {
+    // 逗号分隔
+    device_ids: Option<String>,
+    // 逗号分隔, 前缀匹配, 如 device. 或 coin.payout
+    kinds: Option<String>,
+    // 浏览器重连时会带上 Last-Event-ID 头, 优先使用
+    last_id: Option<u64>,
+}
+
+struct Filter {
+    device_ids: Option<Vec<i64>>,
+    kinds: Option<Vec<String>>,
+}
+
+impl Filter {
+    fn parse(req: &StreamReq) -> Result<Self, AppErr> {
+        let device_ids = match &req.device_ids {
+            Some(ids) => match ids.split(',').map(|v| v.trim().parse()).collect() {
+                Ok(ids) => Some(ids),
+                Err(_) => return validation("无效的设备ID"),
+            },
+            None => None,
+        };
+        let kinds = req
+            .kinds
+            .as_ref()
+            .map(|v| v.split(',').map(|v| v.trim().to_string()).collect());
+        Ok(Filter { device_ids, kinds })
+    }
+
+    fn matches(&self, e: &Event) -> bool {
+        let device = match (&self.device_ids, e.device_id) {
+            (Some(ids), Some(id)) => ids.contains(&id),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let kind = match &self.kinds {
+            Some(kinds) => kinds.iter().any(|v| e.kind.starts_with(v.as_str())),
+            None => true,
+        };
+        device && kind
+    }
+}
+
+// 响应体, 发送端关闭后结束
+struct EventBody(mpsc::Receiver<Bytes>);
+
+impl Stream for EventBody {
+    type Item = Result<Bytes, AppErr>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx).map(|v| v.map(Ok))
+    }
+}
+
+// 缓冲区满时等待, 超时说明客户端处理不过来, 返回 false 断开连接
+async fn send(tx: &mpsc::Sender<Bytes>, frame: Bytes) -> bool {
+    tx.send_timeout(frame, Duration::from_secs(EVENT_SEND_TIMEOUT_SECS))
+        .await
+        .is_ok()
+}
+
+fn to_frame(e: &Event) -> Bytes {
+    let data = serde_json::to_string(e).unwrap_or_default();
+    Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", e.id, e.kind, data))
+}
+
+// 客户端断开或处理过慢时 send 失败, 转发随之结束
+// 等待期间积压的事件由 Lagged 从补发缓存取回
+async fn forward(
+    filter: Filter,
+    replay: Vec<SharedEvent>,
+    mut receiver: Receiver<SharedEvent>,
+    tx: mpsc::Sender<Bytes>,
+    mut last_id: u64,
+) {
+    for e in replay.iter().filter(|e| filter.matches(e)) {
+        if !send(&tx, to_frame(e)).await {
+            return;
+        }
+    }
+    let mut keepalive = time::interval(Duration::from_secs(EVENT_KEEPALIVE_SECS));
+    loop {
+        let events = tokio::select! {
+            ret = receiver.recv() => match ret {
+                Ok(e) => vec![e],
+                Err(RecvError::Lagged(_)) => event::since(last_id),
+                Err(RecvError::Closed) => break,
+            },
+            _ = keepalive.tick() => {
+                if !send(&tx, Bytes::from_static(KEEPALIVE)).await {
+                    break;
+                }
+                continue;
+            }
+        };
+        for e in events {
+            if e.id <= last_id {
+                continue;
+            }
+            last_id = e.id;
+            if filter.matches(&e) && !send(&tx, to_frame(&e)).await {
+                return;
+            }
+        }
+    }
+}
+
+// Server-Sent Events, 每条事件的 data 为 JSON
+// 浏览器 EventSource 不能设置请求头, 可以用 access_token 参数传登录 token
+#[get("/stream")]
+async fn stream(req: HttpRequest, query: Query<StreamReq>) -> Result<HttpResponse, AppErr> {
+    let filter = Filter::parse(&query)?;
+    let last_id = req
+        .headers()
+        .get(HEAD_LAST_EVENT_ID)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .or(query.last_id);
+    let sub = event::subscribe(last_id);
+
+    let (tx, rx) = mpsc::channel(EVENT_CLIENT_BUFFER);
+    ntex::rt::spawn(forward(filter, sub.replay, sub.receiver, tx, sub.last_id));
+
+    let res = HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("cache-control", "no-cache")
+        .streaming(EventBody(rx));
+    Ok(res)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/event").service(stream);
+    cfg.service(scope);
+}
@@ -0,0 +1,73 @@
+use crate::error::validation;
+use crate::store::file;
+use crate::store::firmware::{target, TableFirmware, TableFirmwareUpdate};
+use crate::utils::{sha256_hex, Array};
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+struct UploadReq {
+    target: i32,
+    version: String,
+    hash: String,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+}
+
+#[post("/upload")]
+async fn upload(actor: Actor, req: Cbor<UploadReq>) -> CborRes<i64> {
+    if req.target != target::APP && req.target != target::MCU {
+        return validation("无效的固件类型");
+    }
+    let hash = sha256_hex(&req.data);
+    if !hash.eq_ignore_ascii_case(&req.hash) {
+        return validation("固件校验失败");
+    }
+    file::write(&file::firmware_path(&hash), &req.data).await?;
+    let id = store::firmware::create(req.target, &req.version, &hash, req.data.len() as i64).await?;
+    let firmware = store::firmware::get(id).await?;
+    actor.log("firmware.upload", None, None, json(&firmware)).await;
+    new_cbor(id)
+}
+
+#[post("/select")]
+async fn select() -> CborRes<Array<TableFirmware>> {
+    let firmwares = store::firmware::select().await?;
+    new_cbor(firmwares)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeployReq {
+    firmware_id: i64,
+    device_ids: Array<i64>,
+}
+
+#[post("/deploy")]
+async fn deploy(actor: Actor, req: Cbor<DeployReq>) -> CborRes<Array<i64>> {
+    let mut ids = Vec::with_capacity(req.device_ids.len());
+    for device_id in req.device_ids.iter() {
+        let id = serve::api::firmware::deploy(*device_id, req.firmware_id).await?;
+        actor.log("firmware.deploy", Some(*device_id), None, json(&*req)).await;
+        ids.push(id);
+    }
+    new_cbor(ids.into_boxed_slice())
+}
+
+#[post("/state")]
+async fn state(device_id: Cbor<i64>) -> CborRes<Array<TableFirmwareUpdate>> {
+    let updates = store::firmware::select_update(*device_id).await?;
+    new_cbor(updates)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/firmware")
+        .service(upload)
+        .service(select)
+        .service(deploy)
+        .service(state);
+    cfg.service(scope);
+}
@@ -1,9 +1,28 @@
 use ntex::web::{self, ServiceConfig};
 
+use super::auth::Auth;
+
+mod api_key;
+mod audit;
+mod campaign;
 mod device;
+mod event;
+mod firmware;
+mod profile;
+mod provision;
+mod user;
 
 pub fn register(cfg: &mut ServiceConfig) {
-    let scope = web::scope("/api").configure(device::register);
+    let scope = web::scope("/api")
+        .wrap(Auth)
+        .configure(audit::register)
+        .configure(campaign::register)
+        .configure(device::register)
+        .configure(event::register)
+        .configure(firmware::register)
+        .configure(profile::register)
+        .configure(provision::register)
+        .configure(user::register);
 
     cfg.service(scope);
 }
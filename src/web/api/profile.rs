@@ -0,0 +1,158 @@
+use crate::error::{not_found, AppErr};
+use crate::serve::api::profile::Effective;
+use crate::store::profile::TableProfile;
+use crate::store::setting::{SettingItem, TableSetting};
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use crate::{serve, store};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreateReq {
+    name: String,
+    coin_mask: Option<u32>,
+    bill_mask: Option<u32>,
+    settings: Array<SettingItem>,
+}
+
+#[post("/create")]
+async fn create(actor: Actor, req: Cbor<CreateReq>) -> CborRes<i64> {
+    let id = store::profile::create(&req.name, req.coin_mask, req.bill_mask, &req.settings).await?;
+    actor.log("profile.create", None, None, json(&*req)).await;
+    new_cbor(id)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UpdateReq {
+    id: i64,
+    name: String,
+    coin_mask: Option<u32>,
+    bill_mask: Option<u32>,
+    settings: Array<SettingItem>,
+}
+
+#[post("/update")]
+async fn update(actor: Actor, req: Cbor<UpdateReq>) -> CborRes<()> {
+    let before = load(req.id).await?;
+    let snapshot = serve::api::profile::snapshot(&before.device_ids).await?;
+    store::profile::update(req.id, &req.name, req.coin_mask, req.bill_mask, &req.settings).await?;
+    actor.log("profile.update", None, json(&before), json(&*req)).await;
+    serve::api::profile::propagate(snapshot);
+    new_cbor(())
+}
+
+#[post("/delete")]
+async fn delete(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let before = load(*id).await?;
+    let snapshot = serve::api::profile::snapshot(&before.device_ids).await?;
+    store::profile::delete(*id).await?;
+    actor.log("profile.delete", None, json(&before), None).await;
+    serve::api::profile::propagate(snapshot);
+    new_cbor(())
+}
+
+#[post("/select")]
+async fn select() -> CborRes<Array<TableProfile>> {
+    let profiles = store::profile::select().await?;
+    new_cbor(profiles)
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileInfo {
+    profile: TableProfile,
+    settings: Array<TableSetting>,
+    device_ids: Array<i64>,
+}
+
+async fn load(id: i64) -> Result<ProfileInfo, AppErr> {
+    let profile = store::profile::get(id).await?;
+    let settings = store::profile::select_settings(id).await?;
+    let device_ids = store::profile::select_device_ids(id).await?;
+    Ok(ProfileInfo {
+        profile,
+        settings,
+        device_ids,
+    })
+}
+
+#[post("/get")]
+async fn get(id: Cbor<i64>) -> CborRes<ProfileInfo> {
+    let info = load(*id).await?;
+    new_cbor(info)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AssignReq {
+    profile_id: i64,
+    device_ids: Array<i64>,
+}
+
+#[post("/assign")]
+async fn assign(actor: Actor, req: Cbor<AssignReq>) -> CborRes<()> {
+    store::profile::get(req.profile_id).await?;
+    let mut snapshot = Vec::with_capacity(req.device_ids.len());
+    for device_id in req.device_ids.iter() {
+        let before = serve::api::profile::effective(*device_id).await?;
+        store::profile::assign(req.profile_id, *device_id).await?;
+        actor.log("profile.assign", Some(*device_id), json(&before), json(&*req)).await;
+        snapshot.push((*device_id, before.settings));
+    }
+    serve::api::profile::propagate(snapshot);
+    new_cbor(())
+}
+
+#[post("/unassign")]
+async fn unassign(actor: Actor, device_ids: Cbor<Array<i64>>) -> CborRes<()> {
+    let mut snapshot = Vec::with_capacity(device_ids.len());
+    for device_id in device_ids.iter() {
+        let before = serve::api::profile::effective(*device_id).await?;
+        store::profile::unassign(*device_id).await?;
+        actor.log("profile.unassign", Some(*device_id), json(&before), None).await;
+        snapshot.push((*device_id, before.settings));
+    }
+    serve::api::profile::propagate(snapshot);
+    new_cbor(())
+}
+
+// 为 None 时取消覆盖, 使用配置方案中的值
+#[derive(Debug, Serialize, Deserialize)]
+struct OverrideReq {
+    device_id: i64,
+    coin_mask: Option<u32>,
+    bill_mask: Option<u32>,
+}
+
+#[post("/set_override")]
+async fn set_override(actor: Actor, req: Cbor<OverrideReq>) -> CborRes<()> {
+    let before = serve::api::profile::effective(req.device_id).await?;
+    if !store::profile::set_coin_override(req.device_id, req.coin_mask).await? {
+        return not_found("设备未分配配置方案");
+    }
+    store::profile::set_bill_override(req.device_id, req.bill_mask).await?;
+    actor.log("profile.set_override", Some(req.device_id), json(&before), json(&*req)).await;
+    serve::api::profile::propagate(vec![(req.device_id, before.settings)]);
+    new_cbor(())
+}
+
+#[post("/effective")]
+async fn effective(device_id: Cbor<i64>) -> CborRes<Effective> {
+    let effective = serve::api::profile::effective(*device_id).await?;
+    new_cbor(effective)
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/profile")
+        .service(create)
+        .service(update)
+        .service(delete)
+        .service(select)
+        .service(get)
+        .service(assign)
+        .service(unassign)
+        .service(set_override)
+        .service(effective);
+    cfg.service(scope);
+}
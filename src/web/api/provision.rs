@@ -0,0 +1,51 @@
+use crate::serve::{self, api::provision::Approved};
+use crate::store;
+use crate::store::provision::TablePending;
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::post;
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+// state 为空时返回全部
+#[post("/select")]
+async fn select(state: Cbor<Option<i32>>) -> CborRes<Array<TablePending>> {
+    let pendings = store::provision::select(*state).await?;
+    new_cbor(pendings)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApproveReq {
+    id: i64,
+    name: String,
+    address: String,
+    profile_id: Option<i64>,
+}
+
+#[post("/approve")]
+async fn approve(actor: Actor, req: Cbor<ApproveReq>) -> CborRes<Approved> {
+    let before = store::provision::get(req.id).await?;
+    let approved =
+        serve::api::provision::approve(req.id, &req.name, &req.address, req.profile_id).await?;
+    let device_id = Some(approved.device_id);
+    actor.log("provision.approve", device_id, json(&before), json(&*req)).await;
+    new_cbor(approved)
+}
+
+#[post("/reject")]
+async fn reject(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    let before = store::provision::get(*id).await?;
+    serve::api::provision::reject(*id).await?;
+    let after = store::provision::get(*id).await?;
+    actor.log("provision.reject", None, json(&before), json(&after)).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/provision")
+        .service(select)
+        .service(approve)
+        .service(reject);
+    cfg.service(scope);
+}
@@ -0,0 +1,164 @@
+use crate::error::{conflict, unauthorized, validation, AppErr};
+use crate::store;
+use crate::store::user::{role, TableUser};
+use crate::utils::Array;
+use crate::web::audit::{json, Actor};
+use crate::web::auth::{self, new_password_hash, new_session, verify_password, Session};
+use crate::web::resp::{new_cbor, Cbor, CborRes};
+use ntex::web::{post, HttpRequest};
+use ntex::web::{self, ServiceConfig};
+use serde::{Deserialize, Serialize};
+
+const PASSWORD_MIN_LEN: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct LoginReq {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LoginRes {
+    token: String,
+    expire_timestamp: i64,
+}
+
+#[post("/login")]
+async fn login(http: HttpRequest, req: Cbor<LoginReq>) -> CborRes<LoginRes> {
+    let peer = http.peer_addr().map(|v| v.ip());
+    let user = auth::login(&req.username, &req.password, peer).await?;
+    let (token, expire_timestamp) = new_session(user.id).await?;
+    new_cbor(LoginRes {
+        token,
+        expire_timestamp,
+    })
+}
+
+#[post("/logout")]
+async fn logout(session: Session) -> CborRes<()> {
+    store::user::delete_session(&session.token_hash).await?;
+    new_cbor(())
+}
+
+#[derive(Debug, Serialize)]
+struct MeRes {
+    user_id: i64,
+    username: String,
+    role: i32,
+}
+
+#[post("/me")]
+async fn me(session: Session) -> CborRes<MeRes> {
+    new_cbor(MeRes {
+        user_id: session.user_id,
+        username: session.username,
+        role: session.role,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswordReq {
+    old_password: String,
+    new_password: String,
+}
+
+// 当前会话保留, 其他会话失效
+#[post("/change_password")]
+async fn change_password(actor: Actor, req: Cbor<ChangePasswordReq>) -> CborRes<()> {
+    let session = &actor.session;
+    if req.new_password.len() < PASSWORD_MIN_LEN {
+        return validation("密码长度不能少于8位");
+    }
+    let user = store::user::get(session.user_id).await?;
+    if !verify_password(&req.old_password, &user.salt, &user.password_hash).await? {
+        return unauthorized("原密码错误");
+    }
+    let (password_hash, salt) = new_password_hash(&req.new_password).await?;
+    store::user::set_password(user.id, &password_hash, &salt).await?;
+    store::user::delete_other_sessions(user.id, &session.token_hash).await?;
+    actor.log("user.change_password", None, None, None).await;
+    new_cbor(())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateReq {
+    username: String,
+    password: String,
+    role: i32,
+}
+
+fn check_role(role: i32) -> Result<(), AppErr> {
+    if !(role::VIEWER..=role::ADMIN).contains(&role) {
+        return validation("无效的角色");
+    }
+    Ok(())
+}
+
+#[post("/create")]
+async fn create(actor: Actor, req: Cbor<CreateReq>) -> CborRes<i64> {
+    if req.username.trim().is_empty() {
+        return validation("用户名不能为空");
+    }
+    if req.password.len() < PASSWORD_MIN_LEN {
+        return validation("密码长度不能少于8位");
+    }
+    check_role(req.role)?;
+    if store::user::get_by_name(&req.username).await?.is_some() {
+        return conflict("用户名已存在");
+    }
+    let (password_hash, salt) = new_password_hash(&req.password).await?;
+    let id = store::user::create(&req.username, &password_hash, &salt, req.role).await?;
+    let user = store::user::get(id).await?;
+    actor.log("user.create", None, None, json(&user)).await;
+    new_cbor(id)
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRoleReq {
+    id: i64,
+    role: i32,
+}
+
+#[post("/set_role")]
+async fn set_role(actor: Actor, req: Cbor<SetRoleReq>) -> CborRes<()> {
+    check_role(req.role)?;
+    if req.id == actor.session.user_id {
+        return validation("不能修改当前登录账号的角色");
+    }
+    let before = store::user::get(req.id).await?;
+    store::user::set_role(req.id, req.role).await?;
+    let after = store::user::get(req.id).await?;
+    actor.log("user.set_role", None, json(&before), json(&after)).await;
+    new_cbor(())
+}
+
+#[post("/select")]
+async fn select() -> CborRes<Array<TableUser>> {
+    let users = store::user::select().await?;
+    new_cbor(users)
+}
+
+#[post("/delete")]
+async fn delete(actor: Actor, id: Cbor<i64>) -> CborRes<()> {
+    if *id == actor.session.user_id {
+        return validation("不能删除当前登录的账号");
+    }
+    let before = store::user::get(*id).await?;
+    store::user::delete(*id).await?;
+    actor.log("user.delete", None, json(&before), None).await;
+    new_cbor(())
+}
+
+pub fn register(cfg: &mut ServiceConfig) {
+    let scope = web::scope("/user")
+        .service(login)
+        .service(logout)
+        .service(me)
+        .service(change_password)
+        .service(create)
+        .service(set_role)
+        .service(select)
+        .service(delete)
+        .configure(super::api_key::register);
+    cfg.service(scope);
+}
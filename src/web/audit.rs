@@ -0,0 +1,101 @@
+use ntex::web::{ErrorRenderer, FromRequest, HttpRequest};
+use serde::Serialize;
+
+use super::auth::Session;
+use crate::{
+    error::{AppErr, ErrorExt},
+    serve::api::Operation,
+    store::{self, audit::NewAudit},
+};
+
+// 操作人和来源地址, 写接口通过它记录审计日志
+pub struct Actor {
+    pub session: Session,
+    pub source_ip: String,
+}
+
+pub fn json<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_string(value).ok()
+}
+
+// 没有返回值的命令不记录结果
+fn result_json<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_value(value)
+        .ok()
+        .filter(|v| !v.is_null())
+        .map(|v| v.to_string())
+}
+
+impl Actor {
+    // 远程命令的操作人取当前登录账号
+    pub fn op<'a>(&'a self, reason: &'a str) -> Operation<'a> {
+        Operation {
+            operator: &self.session.username,
+            reason,
+        }
+    }
+
+    // 操作已经生效, 审计写入失败只打印不影响响应
+    pub async fn log(
+        &self,
+        action: &str,
+        device_id: Option<i64>,
+        before: Option<String>,
+        after: Option<String>,
+    ) {
+        self.write(action, device_id, before, after, None).await;
+    }
+
+    // 远程命令不论成败都记录, before 为请求, after 为设备返回的结果, 失败时记录错误信息
+    pub async fn log_ret<T: Serialize>(
+        &self,
+        action: &str,
+        device_id: Option<i64>,
+        before: Option<String>,
+        ret: &Result<T, AppErr>,
+    ) {
+        let (after, err_msg) = match ret {
+            Ok(v) => (result_json(v), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.write(action, device_id, before, after, err_msg).await;
+    }
+
+    async fn write(
+        &self,
+        action: &str,
+        device_id: Option<i64>,
+        before: Option<String>,
+        after: Option<String>,
+        err_msg: Option<String>,
+    ) {
+        let audit = NewAudit {
+            user_id: self.session.user_id,
+            actor: &self.session.username,
+            api_key_id: self.session.api_key_id,
+            action,
+            device_id,
+            before_value: before.as_deref(),
+            after_value: after.as_deref(),
+            err_msg: err_msg.as_deref(),
+            source_ip: &self.source_ip,
+        };
+        store::audit::create(&audit).await.print_if_err();
+    }
+}
+
+impl<E: ErrorRenderer> FromRequest<E> for Actor {
+    type Error = AppErr;
+
+    async fn from_request(
+        req: &HttpRequest,
+        payload: &mut ntex::http::Payload,
+    ) -> Result<Self, Self::Error> {
+        let session = <Session as FromRequest<E>>::from_request(req, payload).await?;
+        let source_ip = req
+            .peer_addr()
+            .map(|v| v.ip().to_string())
+            .unwrap_or_default();
+        Ok(Actor { session, source_ip })
+    }
+}
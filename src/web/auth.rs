@@ -0,0 +1,312 @@
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use ntex::http::HeaderMap;
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{DefaultError, ErrorRenderer, FromRequest, HttpRequest, WebRequest, WebResponse};
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use sha2::Sha256;
+
+use super::perm;
+use crate::{
+    config::{
+        AUTH_FAIL_CACHE_SIZE, BOOTSTRAP_ADMIN, BOOTSTRAP_PASSWORD_PATH, LOGIN_FAIL_IP_MAX,
+        LOGIN_FAIL_USER_MAX, LOGIN_FAIL_WINDOW_SECS, PASSWORD_ROUNDS, SESSION_EXPIRE_SECS,
+    },
+    error::{permission_denied, unauthorized, AppErr, ErrorExt},
+    store::{self, user::{role, TableUser}},
+    utils::{current_timestamp, limit::FailLimiter, sha256_hex, to_hex},
+};
+
+const HEAD_AUTH: &str = "authorization";
+const HEAD_API_KEY: &str = "x-api-key";
+const API_KEY_PREFIX: &str = "osk_";
+const BEARER: &str = "Bearer ";
+
+// 不需要登录即可访问
+const PUBLIC_PATHS: &[&str] = &["/api/user/login"];
+
+// 允许在查询参数中传 token 的接口, 供不能设置请求头的浏览器 EventSource 使用
+const QUERY_TOKEN_PATHS: &[&str] = &["/api/event/stream"];
+const QUERY_TOKEN: &str = "access_token=";
+
+// 使用 API 密钥时 user_id 为密钥创建者, 权限为密钥范围与创建者权限的交集
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: i32,
+    pub perms: u32,
+    pub token_hash: String,
+    pub api_key_id: Option<i64>,
+}
+
+fn random_hex(len: usize) -> String {
+    let mut buf = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut buf);
+    to_hex(&buf)
+}
+
+// 用户不存在时同样计算一次哈希, 响应时间不暴露账号是否存在
+const DUMMY_SALT: &str = "00000000000000000000000000000000";
+
+// PBKDF2 耗时较长, 放到阻塞线程池中执行
+pub async fn hash_password(password: &str, salt: &str) -> Result<String, AppErr> {
+    let password = password.to_string();
+    let salt = salt.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut out = [0u8; 32];
+        pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), PASSWORD_ROUNDS, &mut out);
+        to_hex(&out)
+    })
+    .await
+    .wrap()
+}
+
+pub async fn new_password_hash(password: &str) -> Result<(String, String), AppErr> {
+    let salt = random_hex(16);
+    Ok((hash_password(password, &salt).await?, salt))
+}
+
+fn constant_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn verify_password(password: &str, salt: &str, password_hash: &str) -> Result<bool, AppErr> {
+    Ok(constant_eq(&hash_password(password, salt).await?, password_hash))
+}
+
+static LOGIN_IP_FAILS: OnceLock<FailLimiter> = OnceLock::new();
+static LOGIN_USER_FAILS: OnceLock<FailLimiter> = OnceLock::new();
+
+fn ip_fails() -> &'static FailLimiter {
+    LOGIN_IP_FAILS.get_or_init(|| FailLimiter::new(LOGIN_FAIL_IP_MAX, LOGIN_FAIL_WINDOW_SECS, AUTH_FAIL_CACHE_SIZE))
+}
+
+fn user_fails() -> &'static FailLimiter {
+    LOGIN_USER_FAILS.get_or_init(|| FailLimiter::new(LOGIN_FAIL_USER_MAX, LOGIN_FAIL_WINDOW_SECS, AUTH_FAIL_CACHE_SIZE))
+}
+
+// 按来源地址和用户名分别计数, 任一超出次数都拒绝登录
+pub async fn login(username: &str, password: &str, peer: Option<IpAddr>) -> Result<TableUser, AppErr> {
+    let now = current_timestamp();
+    let ip = peer.map(|v| v.to_canonical().to_string()).unwrap_or_default();
+    if ip_fails().is_limited(&ip, now) || user_fails().is_limited(username, now) {
+        return unauthorized("登录失败次数过多, 请稍后重试");
+    }
+    let user = store::user::get_by_name(username).await?;
+    let ok = match &user {
+        Some(user) => verify_password(password, &user.salt, &user.password_hash).await?,
+        None => {
+            hash_password(password, DUMMY_SALT).await?;
+            false
+        }
+    };
+    match user {
+        Some(user) if ok => {
+            user_fails().clear(username);
+            Ok(user)
+        }
+        _ => {
+            ip_fails().add(ip, now);
+            user_fails().add(username.to_string(), now);
+            unauthorized("用户名或密码错误")
+        }
+    }
+}
+
+// 返回明文 token 和过期时间
+pub async fn new_session(user_id: i64) -> Result<(String, i64), AppErr> {
+    store::user::delete_expired_sessions().await?;
+    let token = random_hex(32);
+    let expire_timestamp = current_timestamp() + SESSION_EXPIRE_SECS;
+    store::user::create_session(&sha256_hex(token.as_bytes()), user_id, expire_timestamp).await?;
+    Ok((token, expire_timestamp))
+}
+
+// 返回明文密钥, 哈希和用于展示的前缀
+pub fn new_api_key() -> (String, String, String) {
+    let key = format!("{}{}", API_KEY_PREFIX, random_hex(24));
+    let key_hash = sha256_hex(key.as_bytes());
+    let key_prefix = key[..API_KEY_PREFIX.len() + 6].to_string();
+    (key, key_hash, key_prefix)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+// token 为十六进制, 不需要解码
+fn query_token<'a>(path: &str, query: &'a str) -> Option<&'a str> {
+    if !QUERY_TOKEN_PATHS.contains(&path) {
+        return None;
+    }
+    query.split('&').find_map(|v| v.strip_prefix(QUERY_TOKEN))
+}
+
+// 权限在此统一校验, 处理函数中不再判断
+fn authorize(session: &Session, path: &str) -> Result<(), AppErr> {
+    let required = perm::required(path);
+    if session.api_key_id.is_some() && required == 0 {
+        return permission_denied("API密钥不能访问账号接口");
+    }
+    if session.perms & required != required {
+        return permission_denied("没有访问权限");
+    }
+    Ok(())
+}
+
+async fn authenticate_key(key: &str, peer: Option<IpAddr>) -> Result<Session, AppErr> {
+    let key_hash = sha256_hex(key.as_bytes());
+    let owner = match store::api_key::get_by_hash(&key_hash).await? {
+        Some(owner) if !owner.key.revoked => owner,
+        _ => return unauthorized("无效的API密钥"),
+    };
+    if let Some(expire) = owner.key.expire_timestamp {
+        if expire <= current_timestamp() {
+            return unauthorized("API密钥已过期");
+        }
+    }
+    if let Some(allowlist) = &owner.key.ip_allowlist {
+        let peer = peer.map(|v| v.to_canonical());
+        let allowed = allowlist
+            .split(',')
+            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .any(|ip| Some(ip.to_canonical()) == peer);
+        if !allowed {
+            return unauthorized("来源地址不在API密钥允许范围内");
+        }
+    }
+    store::api_key::touch(owner.key.id).await?;
+    Ok(Session {
+        user_id: owner.key.user_id,
+        username: owner.username,
+        role: owner.role,
+        perms: owner.key.scopes & perm::granted(owner.role),
+        token_hash: key_hash,
+        api_key_id: Some(owner.key.id),
+    })
+}
+
+async fn authenticate(
+    headers: &HeaderMap,
+    query_token: Option<&str>,
+    peer: Option<IpAddr>,
+) -> Result<Session, AppErr> {
+    if let Some(key) = header(headers, HEAD_API_KEY) {
+        return authenticate_key(key, peer).await;
+    }
+    let token = match header(headers, HEAD_AUTH).and_then(|v| v.strip_prefix(BEARER)).or(query_token) {
+        Some(token) => token,
+        None => return unauthorized("未登录"),
+    };
+    let token_hash = sha256_hex(token.as_bytes());
+    match store::user::get_session(&token_hash).await? {
+        Some(s) => Ok(Session {
+            user_id: s.user_id,
+            username: s.username,
+            role: s.role,
+            perms: perm::granted(s.role),
+            token_hash,
+            api_key_id: None,
+        }),
+        None => unauthorized("登录已过期"),
+    }
+}
+
+pub async fn bootstrap() -> Result<(), AppErr> {
+    if store::user::count().await? > 0 {
+        return Ok(());
+    }
+    let (password, generated) = match std::env::var("ORANGE_BOOTSTRAP_PASSWORD") {
+        Ok(password) if !password.is_empty() => (password, false),
+        _ => (random_hex(8), true),
+    };
+    let (password_hash, salt) = new_password_hash(&password).await?;
+    store::user::create(BOOTSTRAP_ADMIN, &password_hash, &salt, role::ADMIN).await?;
+    if generated {
+        write_bootstrap_password(&password).await?;
+        println!("bootstrap admin:{} password file:{}", BOOTSTRAP_ADMIN, BOOTSTRAP_PASSWORD_PATH);
+    } else {
+        println!("bootstrap admin:{} password from environment", BOOTSTRAP_ADMIN);
+    }
+    Ok(())
+}
+
+// 先删除旧文件, 保证新建时权限为 0600
+async fn write_bootstrap_password(password: &str) -> Result<(), AppErr> {
+    use tokio::io::AsyncWriteExt;
+
+    _ = tokio::fs::remove_file(BOOTSTRAP_PASSWORD_PATH).await;
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(BOOTSTRAP_PASSWORD_PATH)
+        .await?;
+    file.write_all(password.as_bytes()).await?;
+    Ok(())
+}
+
+pub struct Auth;
+
+impl<S> Middleware<S> for Auth {
+    type Service = AuthMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        AuthMiddleware { service }
+    }
+}
+
+pub struct AuthMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<WebRequest<DefaultError>> for AuthMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_poll_ready!(service);
+    ntex::forward_poll_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        if PUBLIC_PATHS.contains(&req.path()) {
+            return ctx.call(&self.service, req).await;
+        }
+        let peer = req.peer_addr().map(|v| v.ip());
+        let query_token = query_token(req.path(), req.query_string());
+        let ret = match authenticate(req.headers(), query_token, peer).await {
+            Ok(session) => authorize(&session, req.path()).map(|_| session),
+            Err(e) => Err(e),
+        };
+        match ret {
+            Ok(session) => {
+                req.extensions_mut().insert(session);
+                ctx.call(&self.service, req).await
+            }
+            Err(e) => Ok(req.render_error(e)),
+        }
+    }
+}
+
+impl<E: ErrorRenderer> FromRequest<E> for Session {
+    type Error = AppErr;
+
+    async fn from_request(
+        req: &HttpRequest,
+        _payload: &mut ntex::http::Payload,
+    ) -> Result<Self, Self::Error> {
+        match req.extensions().get::<Session>() {
+            Some(session) => Ok(session.clone()),
+            None => unauthorized("未登录"),
+        }
+    }
+}
@@ -1,13 +1,18 @@
 use ntex::web::{App, HttpServer};
 
-use crate::{config::WEB_ADDR, error::IoErr};
+use crate::{config::WEB_ADDR, error::AppErr};
 
 mod api;
+mod audit;
+mod auth;
+mod perm;
 mod req;
 mod resp;
 
-pub async fn run() -> Result<(), IoErr> {
+pub async fn run() -> Result<(), AppErr> {
+    auth::bootstrap().await?;
     let app = || App::new().configure(api::register);
     println!("web serve:{}", WEB_ADDR);
-    HttpServer::new(app).bind(WEB_ADDR)?.run().await
+    HttpServer::new(app).bind(WEB_ADDR)?.run().await?;
+    Ok(())
 }
@@ -0,0 +1,98 @@
+use crate::store::user::role;
+
+pub const READ: u32 = 0x01;
+pub const DEVICE_WRITE: u32 = 0x02;
+pub const COIN: u32 = 0x04;
+pub const BILL: u32 = 0x08;
+pub const COMMAND: u32 = 0x10;
+pub const ADMIN: u32 = 0x20;
+
+// 每个接口需要的权限, 0 表示已登录即可访问, 新增接口必须在此登记
+const ROUTES: &[(&str, u32)] = &[
+    ("/api/audit/select", ADMIN),
+    ("/api/campaign/create", DEVICE_WRITE),
+    ("/api/campaign/select", READ),
+    ("/api/campaign/progress", READ),
+    ("/api/campaign/pause", DEVICE_WRITE),
+    ("/api/campaign/resume", DEVICE_WRITE),
+    ("/api/campaign/cancel", DEVICE_WRITE),
+    ("/api/device/create", DEVICE_WRITE),
+    ("/api/device/get", READ),
+    ("/api/device/select", READ),
+    ("/api/device/update", DEVICE_WRITE),
+    ("/api/device/delete", DEVICE_WRITE),
+    ("/api/device/rpc", COMMAND),
+    ("/api/device/auth/issue", ADMIN),
+    ("/api/device/auth/rotate", ADMIN),
+    ("/api/device/auth/fails", READ),
+    ("/api/device/bill/get", READ),
+    ("/api/device/bill/set_mask", BILL),
+    ("/api/device/coin/get", READ),
+    ("/api/device/coin/get_info", READ),
+    ("/api/device/coin/info_history", READ),
+    ("/api/device/coin/set_mask", COIN),
+    ("/api/device/coin/payout", COIN),
+    ("/api/device/coin/payout_logs", READ),
+    ("/api/device/command/reboot", COMMAND),
+    ("/api/device/command/inhibit", COMMAND),
+    ("/api/device/command/enable", COMMAND),
+    ("/api/device/command/logs", READ),
+    ("/api/device/conn/select", READ),
+    ("/api/device/conn/kick", COMMAND),
+    ("/api/device/diagnostic/run", COMMAND),
+    ("/api/device/diagnostic/select", READ),
+    ("/api/device/diagnostic/get", READ),
+    ("/api/device/log/upload", COMMAND),
+    ("/api/device/log/select", READ),
+    ("/api/device/log/download", READ),
+    ("/api/device/log/delete", COMMAND),
+    ("/api/device/setting/get", READ),
+    ("/api/device/setting/set", DEVICE_WRITE),
+    ("/api/device/setting/history", READ),
+    ("/api/device/telemetry/series", READ),
+    ("/api/event/stream", READ),
+    ("/api/firmware/upload", DEVICE_WRITE),
+    ("/api/firmware/select", READ),
+    ("/api/firmware/deploy", DEVICE_WRITE),
+    ("/api/firmware/state", READ),
+    ("/api/profile/create", DEVICE_WRITE),
+    ("/api/profile/update", DEVICE_WRITE),
+    ("/api/profile/delete", DEVICE_WRITE),
+    ("/api/profile/select", READ),
+    ("/api/profile/get", READ),
+    ("/api/profile/assign", DEVICE_WRITE),
+    ("/api/profile/unassign", DEVICE_WRITE),
+    ("/api/profile/set_override", DEVICE_WRITE),
+    ("/api/profile/effective", READ),
+    ("/api/provision/select", READ),
+    ("/api/provision/approve", DEVICE_WRITE),
+    ("/api/provision/reject", DEVICE_WRITE),
+    ("/api/user/logout", 0),
+    ("/api/user/me", 0),
+    ("/api/user/change_password", 0),
+    ("/api/user/create", ADMIN),
+    ("/api/user/set_role", ADMIN),
+    ("/api/user/select", ADMIN),
+    ("/api/user/delete", ADMIN),
+    ("/api/user/api_key/create", ADMIN),
+    ("/api/user/api_key/select", ADMIN),
+    ("/api/user/api_key/revoke", ADMIN),
+];
+
+pub fn granted(role: i32) -> u32 {
+    match role {
+        role::VIEWER => READ,
+        role::OPERATOR => READ | DEVICE_WRITE | COMMAND,
+        role::FINANCE => READ | COIN | BILL,
+        role::ADMIN => READ | DEVICE_WRITE | COIN | BILL | COMMAND | ADMIN,
+        _ => 0,
+    }
+}
+
+// 未登记的接口只允许管理员访问
+pub fn required(path: &str) -> u32 {
+    ROUTES
+        .iter()
+        .find(|(route, _)| *route == path)
+        .map_or(ADMIN, |(_, perm)| *perm)
+}
@@ -1,26 +1,33 @@
-use super::resp::Cbor;
-use crate::error::{AppErr, ErrorExt, error};
+use super::resp::{Cbor, Format};
+use crate::error::{code_err, err_code, AppErr, ErrorExt};
 use ntex::{web::{ErrorRenderer, FromRequest}, util::BytesMut};
 use serde::de::DeserializeOwned;
 
 const MAX_REQ_SIZE: usize = 50 * 1024 * 1024;
+const EMPTY_CBOR_MAP: u8 = 0xA0;
 
 impl<T: DeserializeOwned + 'static, E: ErrorRenderer> FromRequest<E> for Cbor<T> {
     type Error = AppErr;
 
     async fn from_request(
-        _req: &ntex::web::HttpRequest,
+        req: &ntex::web::HttpRequest,
         payload: &mut ntex::http::Payload,
     ) -> Result<Self, Self::Error> {
         let mut buf = BytesMut::new();
         while let Some(item) = payload.recv().await {
             let chunk = item.wrap()?;
             if (buf.len() + chunk.len()) > MAX_REQ_SIZE {
-                return error("请求数据体积过大");
+                return code_err(err_code::PAYLOAD_TOO_LARGE, "请求数据体积过大");
             }
             buf.extend_from_slice(&chunk);
         }
-        let req = serde_cbor::from_slice::<T>(&buf)?;
-        Ok(Cbor(req))
+        // 空请求体按空对象处理, 所有字段都有默认值的请求可以不带请求体
+        let value = match Format::from_content_type(req) {
+            Format::Json if buf.is_empty() => serde_json::from_slice::<T>(b"{}")?,
+            Format::Json => serde_json::from_slice::<T>(&buf)?,
+            _ if buf.is_empty() => serde_cbor::from_slice::<T>(&[EMPTY_CBOR_MAP])?,
+            _ => serde_cbor::from_slice::<T>(&buf)?,
+        };
+        Ok(Cbor(value))
     }
 }
@@ -1,10 +1,10 @@
 use std::ops::{Deref, DerefMut};
 
-use ntex::http::Response;
-use ntex::web::{Responder, WebResponseError};
+use ntex::http::{header, Response, StatusCode};
+use ntex::web::{HttpRequest, Responder, WebResponseError};
 use serde::Serialize;
 
-use crate::error::AppErr;
+use crate::error::{err_code, AppErr};
 
 
 
@@ -13,6 +13,56 @@ const HEAD_ERR: &'static str = "err";
 const HEAD_SUCC: &'static str = "succ";
 
 const CONTENT_TYPE_BIN: &'static str = "application/octet-stream";
+const CONTENT_TYPE_CBOR: &str = "application/cbor";
+const CONTENT_TYPE_JSON: &str = "application/json";
+
+// 未声明格式时按 CBOR 处理, 兼容旧客户端
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Format {
+    Bin,
+    Cbor,
+    Json,
+}
+
+impl Format {
+    fn from_header(req: &HttpRequest, name: header::HeaderName) -> Self {
+        let value = req
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if value.contains(CONTENT_TYPE_JSON) {
+            Self::Json
+        } else if value.contains(CONTENT_TYPE_CBOR) {
+            Self::Cbor
+        } else {
+            Self::Bin
+        }
+    }
+
+    pub(super) fn from_content_type(req: &HttpRequest) -> Self {
+        Self::from_header(req, header::CONTENT_TYPE)
+    }
+
+    pub(super) fn from_accept(req: &HttpRequest) -> Self {
+        Self::from_header(req, header::ACCEPT)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Bin => CONTENT_TYPE_BIN,
+            Self::Cbor => CONTENT_TYPE_CBOR,
+            Self::Json => CONTENT_TYPE_JSON,
+        }
+    }
+
+    fn to_vec<T: Serialize>(self, value: &T) -> Vec<u8> {
+        match self {
+            Self::Json => serde_json::to_vec(value).unwrap(),
+            _ => serde_cbor::to_vec(value).unwrap(),
+        }
+    }
+}
 
 pub struct Cbor<T>(pub T);
 pub struct CborBody(pub Vec<u8>);
@@ -37,22 +87,30 @@ impl<T> DerefMut for Cbor<T> {
 }
 
 impl<T: Serialize> Responder for Cbor<T> {
-    async fn respond_to(self, _req: &ntex::web::HttpRequest) -> Response {
-        let body = serde_cbor::to_vec(&self.0).unwrap();
+    async fn respond_to(self, req: &HttpRequest) -> Response {
+        let format = Format::from_accept(req);
         Response::Ok()
             .set_header(HEAD_RESP, HEAD_SUCC)
-            .content_type(CONTENT_TYPE_BIN)
-            .body(body)
+            .content_type(format.content_type())
+            .body(format.to_vec(&self.0))
     }
 }
 
 impl Responder for CborBody {
 
-    async fn respond_to(self, _req: &ntex::web::HttpRequest) -> Response {
+    async fn respond_to(self, req: &HttpRequest) -> Response {
+        let format = Format::from_accept(req);
+        let body = match format {
+            Format::Json => match serde_cbor::from_slice::<serde_cbor::Value>(&self.0) {
+                Ok(value) => format.to_vec(&value),
+                Err(e) => return AppErr::from(e).error_response(req),
+            },
+            _ => self.0,
+        };
         Response::Ok()
             .set_header(HEAD_RESP, HEAD_SUCC)
-            .content_type(CONTENT_TYPE_BIN)
-            .body(self.0)
+            .content_type(format.content_type())
+            .body(body)
     }
 }
 
@@ -62,24 +120,34 @@ pub fn new_cbor<T>(val: T) -> CborRes<T> {
     Ok(Cbor(val))
 }
 
-#[derive(Debug, Serialize)]
-struct ErrResp {
-    err_code: i32,
-    err_msg: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ErrResp2<'a> {
-    err_code: i32,
-    err_msg: &'a str,
-}
-
 impl WebResponseError for AppErr {
-    fn error_response(&self, _: &ntex::web::HttpRequest) -> Response {
-        let body = self.serial_to_vec();
-        Response::Ok()
+    // 设备返回的错误码不在目录中, 按网关错误处理
+    fn status_code(&self) -> StatusCode {
+        if let AppErr::Custom(_) = self {
+            return StatusCode::BAD_GATEWAY;
+        }
+        match self.code() {
+            err_code::NOT_FOUND => StatusCode::NOT_FOUND,
+            err_code::VALIDATION => StatusCode::BAD_REQUEST,
+            err_code::CONFLICT => StatusCode::CONFLICT,
+            err_code::UNAUTHORIZED => StatusCode::UNAUTHORIZED,
+            err_code::PERMISSION_DENIED => StatusCode::FORBIDDEN,
+            err_code::PAYLOAD_TOO_LARGE => StatusCode::PAYLOAD_TOO_LARGE,
+            err_code::DEVICE_OFFLINE | err_code::BUSY => StatusCode::SERVICE_UNAVAILABLE,
+            err_code::DEVICE_TIMEOUT => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self, req: &HttpRequest) -> Response {
+        let format = Format::from_accept(req);
+        let body = match format {
+            Format::Json => serde_json::to_vec(&self.to_info()).unwrap(),
+            _ => self.serial_to_vec(),
+        };
+        Response::build(self.status_code())
             .set_header(HEAD_RESP, HEAD_ERR)
-            .content_type(CONTENT_TYPE_BIN)
+            .content_type(format.content_type())
             .body(body)
     }
 }